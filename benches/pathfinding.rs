@@ -0,0 +1,131 @@
+// Plain A* against the flow field and chunk graph on 256x256 maps.
+//
+//     cargo bench --bench pathfinding
+
+use criterion::{Criterion, black_box, criterion_group, criterion_main};
+use droneforge::pathfinding::find_path;
+use droneforge::*;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+const SIZE: i32 = 256;
+const AGENTS: usize = 50;
+
+// Open cave: a quarter of the tiles are stone pillars, which keeps the map
+// connected while forcing real detours.
+fn cave() -> World {
+    let mut rng = StdRng::seed_from_u64(11);
+    let mut world = World::new(SIZE, SIZE, 1, TileKind::Air);
+    for y in 0..SIZE {
+        for x in 0..SIZE {
+            if rng.gen_bool(0.25) {
+                world.set_tile(TileCoord3::new(x, y, 0), TileKind::Stone);
+            }
+        }
+    }
+    world.set_tile(core(), TileKind::Floor);
+    world
+}
+
+fn core() -> TileCoord3 {
+    TileCoord3::new(SIZE / 2, SIZE / 2, 0)
+}
+
+// Spawn points along the map edge, the way a wave would arrive.
+fn spawns(world: &World) -> Vec<TileCoord3> {
+    let mut rng = StdRng::seed_from_u64(5);
+    let mut out = Vec::new();
+    while out.len() < AGENTS {
+        let t = rng.gen_range(0..SIZE);
+        let c = match rng.gen_range(0..4) {
+            0 => TileCoord3::new(t, 0, 0),
+            1 => TileCoord3::new(t, SIZE - 1, 0),
+            2 => TileCoord3::new(0, t, 0),
+            _ => TileCoord3::new(SIZE - 1, t, 0),
+        };
+        if world.is_passable(c) && find_path(world, c, core()).is_some() {
+            out.push(c);
+        }
+    }
+    out
+}
+
+fn wave_to_core(c: &mut Criterion) {
+    let world = cave();
+    let starts = spawns(&world);
+    let mut group = c.benchmark_group("wave_to_core_256");
+    group.sample_size(10);
+    group.bench_function("astar_per_agent", |b| {
+        b.iter(|| {
+            for s in &starts {
+                black_box(find_path(&world, *s, core()));
+            }
+        })
+    });
+    group.bench_function("flow_field_build_and_walk", |b| {
+        b.iter(|| {
+            let field = FlowField::new(&world, &[core()]);
+            for s in &starts {
+                let mut at = *s;
+                while let Some(next) = field.next_step(&world, at) {
+                    at = next;
+                }
+                black_box(at);
+            }
+        })
+    });
+    group.finish();
+}
+
+fn repair_after_dig(c: &mut Criterion) {
+    let world = cave();
+    let field = FlowField::new(&world, &[core()]);
+    let dig = TileCoord3::new(SIZE / 2 + 20, SIZE / 2, 0);
+    let mut group = c.benchmark_group("flow_field_dig_256");
+    group.sample_size(10);
+    group.bench_function("incremental_update", |b| {
+        b.iter_batched(
+            || (world.clone(), field.clone()),
+            |(mut world, mut field)| {
+                world.set_tile(dig, TileKind::Wall);
+                field.update(&world);
+                black_box(field.distance(TileCoord3::new(0, 0, 0)));
+            },
+            criterion::BatchSize::LargeInput,
+        )
+    });
+    group.bench_function("full_rebuild", |b| {
+        b.iter_batched(
+            || world.clone(),
+            |mut world| {
+                world.set_tile(dig, TileKind::Wall);
+                black_box(FlowField::new(&world, &[core()]));
+            },
+            criterion::BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+fn long_drone_trip(c: &mut Criterion) {
+    let world = cave();
+    let graph = ChunkGraph::new(&world);
+    let start = spawns(&world)[0];
+    let goal = TileCoord3::new(SIZE - 1 - start.x, SIZE - 1 - start.y, 0);
+    let goal = if world.is_passable(goal) {
+        goal
+    } else {
+        core()
+    };
+    let mut group = c.benchmark_group("long_trip_256");
+    group.sample_size(10);
+    group.bench_function("astar", |b| {
+        b.iter(|| black_box(find_path(&world, start, goal)))
+    });
+    group.bench_function("chunk_graph", |b| {
+        b.iter(|| black_box(graph.find_path(&world, start, goal)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, wave_to_core, repair_after_dig, long_drone_trip);
+criterion_main!(benches);
@@ -0,0 +1,96 @@
+// Whole engine ticks with 10, 100 and 1000 drones on a 256x256x8 map, with
+// path planning on one thread and on all of them.
+//
+//     cargo bench --bench tick
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use droneforge::*;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+
+const SIZE: i32 = 256;
+const LEVELS: i32 = 8;
+const TICKS: u64 = 5;
+
+// Solid rock with an open cave on level 0: a fifth of it is stone pillars
+// to mine and walk around, with the core on floor in the middle.
+fn cave() -> World {
+    let mut rng = StdRng::seed_from_u64(11);
+    let mut world = World::new(SIZE, SIZE, LEVELS, TileKind::Stone);
+    for y in 0..SIZE {
+        for x in 0..SIZE {
+            if !rng.gen_bool(0.2) {
+                world.set_tile(TileCoord3::new(x, y, 0), TileKind::Air);
+            }
+        }
+    }
+    let core = TileCoord3::new(SIZE / 2, SIZE / 2, 0);
+    world.set_tile(core, TileKind::Floor);
+    world.set_core(core);
+    world
+}
+
+fn open_tile(world: &World, rng: &mut StdRng) -> TileCoord3 {
+    loop {
+        let c = TileCoord3::new(rng.gen_range(0..SIZE), rng.gen_range(0..SIZE), 0);
+        if world.is_passable(c) {
+            return c;
+        }
+    }
+}
+
+// `drones` drones scattered over the cave, half of them sent across the
+// map and half digging out pillars, which changes the map as they go.
+fn game(drones: usize) -> Engine {
+    let mut rng = StdRng::seed_from_u64(drones as u64);
+    let world = cave();
+    let crowd = (0..drones)
+        .map(|i| Drone::new(i as u32 + 1).at(open_tile(&world, &mut rng)))
+        .collect();
+    let mut engine = Engine::new(world, crowd);
+    for i in 0..drones {
+        let task = if i % 2 == 0 {
+            Task::MoveTo(open_tile(&engine.world, &mut rng))
+        } else {
+            let x = rng.gen_range(0..SIZE - 2);
+            let y = rng.gen_range(0..SIZE - 2);
+            let min = TileCoord3::new(x, y, 0);
+            Task::MineBox(TileBox3::new(min, TileCoord3::new(x + 2, y + 2, 0)))
+        };
+        engine.queue_task(task);
+    }
+    // Hand out the tasks
+    engine.tick();
+    engine
+}
+
+fn ticks(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tick_256x256x8");
+    group.sample_size(10);
+    for drones in [10, 100, 1000] {
+        let save = game(drones).to_save();
+        group.throughput(Throughput::Elements(drones as u64 * TICKS));
+        for (label, threads) in [("one_thread", 1), ("all_threads", 0)] {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            group.bench_with_input(BenchmarkId::new(label, drones), &save, |b, save| {
+                b.iter_batched(
+                    || Engine::from_save(save.clone()),
+                    |mut engine| {
+                        pool.install(|| {
+                            for _ in 0..TICKS {
+                                engine.tick();
+                            }
+                        })
+                    },
+                    criterion::BatchSize::LargeInput,
+                );
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, ticks);
+criterion_main!(benches);
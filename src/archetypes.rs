@@ -0,0 +1,128 @@
+use serde::{Deserialize, Serialize};
+
+use crate::resources::Resources;
+use crate::tasks::TaskKind;
+
+// A drone type described as data: the stats a new drone of this type starts
+// with and the kinds of task the scheduler may give it. Scenarios can add
+// their own types alongside the built-in ones.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Archetype {
+    pub name: String,
+    // Tiles moved per tick
+    pub speed: u32,
+    // How hard the drone digs; zero means it can't mine at all
+    pub mining_power: u32,
+    pub cargo_capacity: u32,
+    pub battery_capacity: u32,
+    pub accepts: Vec<TaskKind>,
+    // What the core spends to fabricate one, and how many ticks it takes
+    pub cost: Resources,
+    pub build_time: u32,
+}
+
+impl Archetype {
+    pub fn accepts(&self, kind: TaskKind) -> bool {
+        self.accepts.contains(&kind)
+    }
+
+    pub fn miner() -> Self {
+        Self {
+            name: "Miner".to_string(),
+            speed: 1,
+            mining_power: 4,
+            cargo_capacity: 20,
+            battery_capacity: 200,
+            accepts: vec![TaskKind::Mine, TaskKind::Haul, TaskKind::Move],
+            cost: Resources { stone: 6, iron: 4 },
+            build_time: 20,
+        }
+    }
+
+    pub fn builder() -> Self {
+        Self {
+            name: "Builder".to_string(),
+            speed: 1,
+            mining_power: 1,
+            cargo_capacity: 10,
+            battery_capacity: 200,
+            accepts: vec![
+                TaskKind::Build,
+                TaskKind::Haul,
+                TaskKind::Move,
+                TaskKind::Repair,
+            ],
+            cost: Resources { stone: 8, iron: 3 },
+            build_time: 20,
+        }
+    }
+
+    pub fn hauler() -> Self {
+        Self {
+            name: "Hauler".to_string(),
+            speed: 2,
+            mining_power: 0,
+            cargo_capacity: 40,
+            battery_capacity: 250,
+            accepts: vec![TaskKind::Haul, TaskKind::Move],
+            cost: Resources { stone: 6, iron: 2 },
+            build_time: 15,
+        }
+    }
+
+    pub fn scout() -> Self {
+        Self {
+            name: "Scout".to_string(),
+            speed: 3,
+            mining_power: 0,
+            cargo_capacity: 0,
+            battery_capacity: 300,
+            accepts: vec![TaskKind::Move, TaskKind::Guard],
+            cost: Resources { stone: 2, iron: 2 },
+            build_time: 10,
+        }
+    }
+}
+
+pub fn builtin_archetypes() -> Vec<Archetype> {
+    vec![
+        Archetype::miner(),
+        Archetype::builder(),
+        Archetype::hauler(),
+        Archetype::scout(),
+    ]
+}
+
+// Case-insensitive lookup, e.g. "miner" or "Scout".
+pub fn find_archetype<'a>(archetypes: &'a [Archetype], name: &str) -> Option<&'a Archetype> {
+    archetypes
+        .iter()
+        .find(|a| a.name.eq_ignore_ascii_case(name.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtins_specialize_and_load_from_json() {
+        let all = builtin_archetypes();
+        let miner = find_archetype(&all, "miner").unwrap();
+        assert!(miner.accepts(TaskKind::Mine));
+        assert!(!miner.accepts(TaskKind::Build));
+        let scout = find_archetype(&all, "SCOUT").unwrap();
+        assert!(scout.speed > miner.speed);
+        assert!(!scout.accepts(TaskKind::Haul));
+        assert!(find_archetype(&all, "warrior").is_none());
+
+        let custom: Vec<Archetype> = serde_json::from_str(
+            r#"[{ "name": "Digger", "speed": 1, "mining_power": 5,
+                  "cargo_capacity": 5, "battery_capacity": 100,
+                  "accepts": ["Mine"], "cost": { "stone": 3, "iron": 1 },
+                  "build_time": 12 }]"#,
+        )
+        .unwrap();
+        assert_eq!(custom[0].mining_power, 5);
+        assert!(custom[0].accepts(TaskKind::Mine));
+    }
+}
@@ -0,0 +1,175 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::command::{Command, CommandRecord};
+use crate::console::parse_console_command;
+use crate::drones::DroneStatus;
+use crate::dsl_ast::Program;
+use crate::engine::Engine;
+use crate::resources::Resources;
+use crate::session::SessionState;
+use crate::tasks::TaskState;
+
+// A command script for a headless run that couldn't be read.
+#[derive(Debug, Error)]
+pub enum BatchError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Line {line}: {message}")]
+    Line { line: usize, message: String },
+    #[error("Couldn't read {0}: {1}")]
+    Json(String, String),
+}
+
+// Parses a command script, one console command per line:
+//   # dig out the first room
+//   mine (10,10) to (14,14)
+//   @200 drone 2: role hauler
+//   @200 run programs/patrol.json
+// A leading "@N" gives the command at tick N, otherwise it goes with the
+// line before, starting at tick 0. "run FILE" starts a program from a JSON
+// file, relative to `dir`.
+pub fn parse_command_script(text: &str, dir: &Path) -> Result<Vec<CommandRecord>, BatchError> {
+    let mut records = Vec::new();
+    let mut tick = 0;
+    for (i, line) in text.lines().enumerate() {
+        let error = |message: String| BatchError::Line {
+            line: i + 1,
+            message,
+        };
+        let mut line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(rest) = line.strip_prefix('@') {
+            let (at, rest) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            let at: u64 = at.parse().map_err(|_| error(format!("Bad tick: {}", at)))?;
+            if at < tick {
+                return Err(error(format!("Tick {} comes before tick {}", at, tick)));
+            }
+            tick = at;
+            line = rest.trim();
+        }
+        let command = match line.strip_prefix("run ") {
+            Some(file) => {
+                let path = dir.join(file.trim());
+                let text = fs::read_to_string(&path)
+                    .map_err(|e| error(format!("{}: {}", path.display(), e)))?;
+                let program: Program = serde_json::from_str(&text)
+                    .map_err(|e| error(format!("{}: {}", path.display(), e)))?;
+                Command::RunProgram(program)
+            }
+            None => parse_console_command(line)
+                .map_err(|e| error(e.to_string()))?
+                .into(),
+        };
+        records.push(CommandRecord { tick, command });
+    }
+    Ok(records)
+}
+
+// Reads a command script, or a JSON list of commands such as a replay's.
+pub fn load_command_script(path: impl AsRef<Path>) -> Result<Vec<CommandRecord>, BatchError> {
+    let path = path.as_ref();
+    let text = fs::read_to_string(path)?;
+    if path.extension().is_some_and(|e| e == "json") {
+        return serde_json::from_str(&text)
+            .map_err(|e| BatchError::Json(path.display().to_string(), e.to_string()));
+    }
+    parse_command_script(&text, path.parent().unwrap_or(Path::new(".")))
+}
+
+// Where a game stands at the end of a headless run, for comparing runs
+// across seeds and balance changes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RunStats {
+    pub outcome: SessionState,
+    pub ticks: u64,
+    pub waves_survived: u32,
+    pub resources: Resources,
+    pub core_hp: (u32, u32),
+    pub drones: usize,
+    pub broken_drones: usize,
+    pub tasks_done: usize,
+    pub tasks_open: usize,
+    pub tasks_cancelled: usize,
+    pub state_hash: u64,
+}
+
+impl RunStats {
+    pub fn collect(engine: &Engine) -> Self {
+        let tasks = |want: &[TaskState]| {
+            engine
+                .tasks
+                .tasks
+                .iter()
+                .filter(|(_, state)| want.contains(state))
+                .count()
+        };
+        Self {
+            outcome: engine.session_state(),
+            ticks: engine.clock.tick(),
+            waves_survived: engine.waves_survived(),
+            resources: engine.world.resources,
+            core_hp: engine.world.core_hp(),
+            drones: engine.drones.len(),
+            broken_drones: engine
+                .drones
+                .iter()
+                .filter(|d| d.status == DroneStatus::Broken)
+                .count(),
+            tasks_done: tasks(&[TaskState::Done]),
+            tasks_open: tasks(&[TaskState::Pending, TaskState::InProgress]),
+            tasks_cancelled: tasks(&[TaskState::Cancelled]),
+            state_hash: engine.state_hash(),
+        }
+    }
+}
+
+impl fmt::Display for RunStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Outcome: {}", self.outcome.label())?;
+        writeln!(f, "Ticks: {}", self.ticks)?;
+        writeln!(f, "Waves survived: {}", self.waves_survived)?;
+        writeln!(
+            f,
+            "Resources: {} stone, {} iron",
+            self.resources.stone, self.resources.iron
+        )?;
+        writeln!(f, "Core HP: {}/{}", self.core_hp.0, self.core_hp.1)?;
+        writeln!(f, "Drones: {} ({} broken)", self.drones, self.broken_drones)?;
+        writeln!(
+            f,
+            "Tasks: {} done, {} open, {} cancelled",
+            self.tasks_done, self.tasks_open, self.tasks_cancelled
+        )?;
+        write!(f, "State hash: {:016x}", self.state_hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn script_lines_carry_their_tick_forward() {
+        let text = "# setup\nmine (1,1) to (3,3)\n@50 fabricate miner\ndrone 1: role hauler\n";
+        let records = parse_command_script(text, Path::new(".")).unwrap();
+        let ticks: Vec<_> = records.iter().map(|r| r.tick).collect();
+        assert_eq!(ticks, [0, 50, 50]);
+        assert!(matches!(records[0].command, Command::QueueProgram(_)));
+        assert!(matches!(
+            records[2].command,
+            Command::SetRole { drone: 1, .. }
+        ));
+
+        let err = parse_command_script("@9 mine (1,1) to (2,2)\n@3 fab miner", Path::new("."))
+            .unwrap_err();
+        assert!(matches!(err, BatchError::Line { line: 2, .. }));
+    }
+}
@@ -2,32 +2,43 @@ use bevy::prelude::Camera2d;
 use bevy::prelude::*;
 use bevy::window::PrimaryWindow;
 use bevy_egui::{EguiContexts, EguiPlugin, EguiPrimaryContextPass, egui};
-use droneforge::hud::{HUD_PAUSE_LABEL, HUD_Z_DOWN_LABEL, HUD_Z_UP_LABEL};
+use droneforge::command::Command as GameCommand;
+use droneforge::console::area_func_for_text;
+use droneforge::history::{DEFAULT_HISTORY_BUDGET, DEFAULT_SNAPSHOT_INTERVAL};
+use droneforge::hud::{
+    HUD_PAUSE_LABEL, HUD_Z_DOWN_LABEL, HUD_Z_UP_LABEL, format_cargo, format_objectives,
+    format_wave_label, task_holder,
+};
+use droneforge::replay::DEFAULT_HASH_INTERVAL;
 use droneforge::world::World as GameWorld;
 use droneforge::*;
 
 // ---------- Constants ----------
 const TILE_SIZE: f32 = 16.0;
 const INITIAL_Z_LEVEL: i32 = 0;
-const WORLD_WIDTH: i32 = 64;
-const WORLD_HEIGHT: i32 = 64;
-const WORLD_LEVELS: i32 = 1;
-const RNG_SEED: u64 = 42;
+const DEFAULT_SCENARIO: &str = include_str!("../../scenarios/default.json");
+const SAVE_PATH: &str = "droneforge.sav";
+const AUTOSAVE_PATH: &str = "droneforge-autosave.sav";
+const REPLAY_PATH: &str = "droneforge.replay";
+// Five minutes at normal speed
+const AUTOSAVE_EVERY_TICKS: u64 = 3000;
 
 // ---------- Components ----------
-#[derive(Component)]
-struct TilePos {
-    x: i32,
-    y: i32,
-    z: i32,
-}
-
 #[derive(Component)]
 struct TilesLayer; // Marker to despawn/rebuild when Z changes
 
 #[derive(Component)]
 struct SelectionOverlay; // Marker for selection rectangle overlay
 
+#[derive(Component)]
+struct ReservationOverlay; // Marker for tiles claimed by running tasks
+
+#[derive(Component)]
+struct ItemOverlay; // Marker for stockpile zones and loose item piles
+
+#[derive(Component)]
+struct DroneSprite; // Marker for drones drawn at their positions
+
 // ---------- Resources ----------
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Tool {
@@ -41,8 +52,6 @@ struct UiState {
     console_input: String,
     console_log: Vec<String>,
     focus_console: bool,
-    paused: bool,
-    wave_label: String,
     current_z: i32,
     request_rebuild_tiles: bool,
     current_tool: Tool,
@@ -57,13 +66,28 @@ struct SelectionState {
     last_box: Option<TileBox3>,
 }
 
+// Sprite entity for each tile on the level being shown
+#[derive(Resource, Default)]
+struct TileSprites(std::collections::HashMap<TileCoord3, Entity>);
+
 #[derive(Resource)]
 struct GameEngine {
     engine: Engine,
 }
 
+// What the current game was built from, for restarting it
+#[derive(Resource)]
+struct GameSetup {
+    scenario: Scenario,
+    seed: Option<u64>,
+}
+
 // ---------- Entry ----------
 fn main() {
+    let setup = GameSetup {
+        scenario: starting_scenario(),
+        seed: None,
+    };
     App::new()
         .add_plugins(DefaultPlugins.set(WindowPlugin {
             primary_window: Some(Window {
@@ -79,25 +103,17 @@ fn main() {
             console_input: String::new(),
             console_log: vec!["Welcome to Droneforge GUI (MVP)".to_string()],
             focus_console: false,
-            paused: false,
-            wave_label: "Wave 1 in 00:00".to_string(),
             current_z: INITIAL_Z_LEVEL,
             request_rebuild_tiles: true,
             current_tool: Tool::Select,
             toast: None,
         })
         .insert_resource(SelectionState::default())
+        .init_resource::<TileSprites>()
         .insert_resource(GameEngine {
-            engine: Engine::new(
-                GameWorld::from_seed_with_distribution(
-                    WORLD_WIDTH,
-                    WORLD_HEIGHT,
-                    WORLD_LEVELS,
-                    RNG_SEED,
-                ),
-                vec![Drone::new(1)],
-            ),
+            engine: new_game(&setup),
         })
+        .insert_resource(setup)
         // Setup
         .add_systems(Startup, setup_camera)
         // Frame systems
@@ -107,19 +123,49 @@ fn main() {
                 handle_pan_zoom,
                 handle_selection_input,
                 build_tiles_when_needed,
-                update_tile_colors_from_world,
+                apply_engine_events,
+                update_reservation_overlay,
+                update_item_overlay,
+                update_drone_sprites,
                 tick_engine_when_running,
                 update_toast_timer,
             ),
         )
-        .add_systems(EguiPrimaryContextPass, draw_ui)
+        .add_systems(EguiPrimaryContextPass, (draw_ui, draw_end_screen).chain())
         .run();
 }
 
 // ---------- Setup ----------
-fn setup_camera(mut commands: Commands) {
-    let center_x = (WORLD_WIDTH as f32) * TILE_SIZE * 0.5;
-    let center_y = (WORLD_HEIGHT as f32) * TILE_SIZE * 0.5;
+// The scenario named on the command line, or the bundled default map.
+fn starting_scenario() -> Scenario {
+    match std::env::args().nth(1) {
+        Some(path) => Scenario::load(&path)
+            .unwrap_or_else(|e| panic!("Couldn't load scenario {}: {}", path, e)),
+        None => Scenario::from_json(DEFAULT_SCENARIO).expect("bundled scenario is valid"),
+    }
+}
+
+fn new_game(setup: &GameSetup) -> Engine {
+    let scenario = &setup.scenario;
+    let mut engine = match setup.seed {
+        Some(seed) => scenario.build_with_seed(seed),
+        None => scenario.build(),
+    }
+    .unwrap_or_else(|e| panic!("Scenario {}: {}", scenario.name, e));
+    // Every session is recorded so a bug can be sent in as a replay
+    engine.start_recording(DEFAULT_HASH_INTERVAL);
+    engine.enable_history(DEFAULT_SNAPSHOT_INTERVAL, DEFAULT_HISTORY_BUDGET);
+    engine
+}
+
+fn setup_camera(mut commands: Commands, eng: Res<GameEngine>) {
+    let world = &eng.engine.world;
+    // Start over the core, or the middle of the map without one
+    let focus = world
+        .core()
+        .unwrap_or(TileCoord3::new(world.width() / 2, world.height() / 2, 0));
+    let center_x = focus.x as f32 * TILE_SIZE + TILE_SIZE * 0.5;
+    let center_y = focus.y as f32 * TILE_SIZE + TILE_SIZE * 0.5;
     commands.spawn((
         Camera::default(),
         Camera2d,
@@ -137,9 +183,28 @@ fn tile_color_for_kind(k: TileKind) -> Color {
         TileKind::Iron => Color::srgb(0.8, 0.45, 0.2),
         TileKind::Wall => Color::srgb(0.15, 0.15, 0.18),
         TileKind::Floor => Color::srgb(0.25, 0.25, 0.28),
+        TileKind::Stairs => Color::srgb(0.45, 0.35, 0.55),
+        TileKind::Charger => Color::srgb(0.2, 0.75, 0.45),
     }
 }
 
+// Partly mined tiles fade toward a pale crack color as they take damage.
+fn tile_color(world: &GameWorld, c: TileCoord3) -> Color {
+    let base = tile_color_for_kind(world.get_tile(c).unwrap_or(TileKind::Air));
+    let t = world.mined_fraction(c);
+    if t <= 0.0 {
+        return base;
+    }
+    let base = base.to_srgba();
+    let crack = Srgba::rgb(0.95, 0.88, 0.65);
+    let mix = 0.25 + 0.5 * t;
+    Color::srgb(
+        base.red + (crack.red - base.red) * mix,
+        base.green + (crack.green - base.green) * mix,
+        base.blue + (crack.blue - base.blue) * mix,
+    )
+}
+
 fn world_to_tile_coord(p: Vec2) -> (i32, i32) {
     let x = (p.x / TILE_SIZE).floor() as i32;
     let y = (p.y / TILE_SIZE).floor() as i32;
@@ -165,6 +230,7 @@ fn build_tiles_when_needed(
     mut commands: Commands,
     mut ui: ResMut<UiState>,
     engine: Res<GameEngine>,
+    mut sprites: ResMut<TileSprites>,
     existing_layers: Query<Entity, With<TilesLayer>>,
 ) {
     if !ui.request_rebuild_tiles {
@@ -174,22 +240,18 @@ fn build_tiles_when_needed(
     for e in &existing_layers {
         commands.entity(e).despawn();
     }
+    sprites.0.clear();
     // Build current z layer tiles
     let z = ui.current_z;
     for y in 0..engine.engine.world.height() {
         for x in 0..engine.engine.world.width() {
-            let k = engine
-                .engine
-                .world
-                .get_tile(TileCoord3 { x, y, z })
-                .unwrap_or(TileKind::Air);
-            let color = tile_color_for_kind(k);
+            let color = tile_color(&engine.engine.world, TileCoord3 { x, y, z });
             let pos = Vec3::new(
                 x as f32 * TILE_SIZE + TILE_SIZE * 0.5,
                 y as f32 * TILE_SIZE + TILE_SIZE * 0.5,
                 0.0,
             );
-            let _id = commands
+            let id = commands
                 .spawn((
                     Sprite {
                         color,
@@ -198,29 +260,176 @@ fn build_tiles_when_needed(
                     },
                     Transform::from_translation(pos),
                     GlobalTransform::default(),
-                    TilePos { x, y, z },
                     TilesLayer,
                 ))
                 .id();
+            sprites.0.insert(TileCoord3 { x, y, z }, id);
         }
     }
     // Done
     ui.request_rebuild_tiles = false;
 }
 
-fn update_tile_colors_from_world(engine: Res<GameEngine>, mut q: Query<(&TilePos, &mut Sprite)>) {
-    if !engine.is_changed() {
-        // We still refresh, but this early return could be enabled if we track diffs
+// Repaints only the tiles the engine reports as changed and logs task
+// outcomes to the console.
+fn apply_engine_events(
+    mut eng: ResMut<GameEngine>,
+    mut ui: ResMut<UiState>,
+    sprites: Res<TileSprites>,
+    mut q: Query<&mut Sprite, With<TilesLayer>>,
+) {
+    for event in eng.engine.drain_events() {
+        match event {
+            EngineEvent::TileChanged { pos, .. } => {
+                if let Some(&e) = sprites.0.get(&pos)
+                    && let Ok(mut sprite) = q.get_mut(e)
+                {
+                    sprite.color = tile_color(&eng.engine.world, pos);
+                }
+            }
+            EngineEvent::TaskCompleted { task } => {
+                ui.console_log.push(format!("Task #{} done", task));
+            }
+            EngineEvent::TaskFailed { task, reason } => {
+                ui.console_log
+                    .push(format!("Task #{} back in queue: {}", task, reason));
+            }
+            EngineEvent::DroneFabricated { drone } => {
+                ui.console_log.push(format!("Drone #{} rolled out", drone));
+            }
+            EngineEvent::EnemySpawned { wave, enemies } => {
+                ui.console_log
+                    .push(format!("Wave {}: {} enemies attack", wave, enemies));
+            }
+            EngineEvent::CoreDamaged { hp, max } => {
+                ui.console_log.push(format!("Core hit: {}/{}", hp, max));
+            }
+            _ => {}
+        }
+    }
+}
+
+fn update_reservation_overlay(
+    mut commands: Commands,
+    ui: Res<UiState>,
+    engine: Res<GameEngine>,
+    existing: Query<Entity, With<ReservationOverlay>>,
+) {
+    // Reserved tile counts are small; rebuilding each frame keeps this simple
+    for e in &existing {
+        commands.entity(e).despawn();
+    }
+    for (c, _task) in engine.engine.reservations.iter() {
+        if c.z != ui.current_z {
+            continue;
+        }
+        let pos = Vec3::new(
+            c.x as f32 * TILE_SIZE + TILE_SIZE * 0.5,
+            c.y as f32 * TILE_SIZE + TILE_SIZE * 0.5,
+            5.0,
+        );
+        commands.spawn((
+            Sprite {
+                color: Color::srgba(1.0, 0.85, 0.2, 0.25),
+                custom_size: Some(Vec2::new(TILE_SIZE, TILE_SIZE)),
+                ..Default::default()
+            },
+            Transform::from_translation(pos),
+            GlobalTransform::default(),
+            ReservationOverlay,
+        ));
+    }
+}
+
+fn update_item_overlay(
+    mut commands: Commands,
+    ui: Res<UiState>,
+    engine: Res<GameEngine>,
+    existing: Query<Entity, With<ItemOverlay>>,
+) {
+    for e in &existing {
+        commands.entity(e).despawn();
+    }
+    let world = &engine.engine.world;
+    let tile_center = |c: TileCoord3, z: f32| {
+        Vec3::new(
+            c.x as f32 * TILE_SIZE + TILE_SIZE * 0.5,
+            c.y as f32 * TILE_SIZE + TILE_SIZE * 0.5,
+            z,
+        )
+    };
+    for zone in world.stockpiles() {
+        for c in zone.iter_tiles().filter(|c| c.z == ui.current_z) {
+            commands.spawn((
+                Sprite {
+                    color: Color::srgba(0.3, 0.8, 0.4, 0.18),
+                    custom_size: Some(Vec2::new(TILE_SIZE, TILE_SIZE)),
+                    ..Default::default()
+                },
+                Transform::from_translation(tile_center(c, 3.0)),
+                GlobalTransform::default(),
+                ItemOverlay,
+            ));
+        }
+    }
+    for (c, items) in world.ground_items() {
+        if c.z != ui.current_z {
+            continue;
+        }
+        // Iron-heavy piles use the ore color, otherwise stone
+        let color = if items.iron >= items.stone {
+            Color::srgb(0.95, 0.6, 0.3)
+        } else {
+            Color::srgb(0.85, 0.85, 0.9)
+        };
+        commands.spawn((
+            Sprite {
+                color,
+                custom_size: Some(Vec2::new(TILE_SIZE * 0.35, TILE_SIZE * 0.35)),
+                ..Default::default()
+            },
+            Transform::from_translation(tile_center(c, 4.0)),
+            GlobalTransform::default(),
+            ItemOverlay,
+        ));
+    }
+}
+
+fn update_drone_sprites(
+    mut commands: Commands,
+    ui: Res<UiState>,
+    engine: Res<GameEngine>,
+    existing: Query<Entity, With<DroneSprite>>,
+) {
+    for e in &existing {
+        commands.entity(e).despawn();
     }
-    for (pos, mut sprite) in &mut q {
-        if let Some(k) = engine.engine.world.get_tile(TileCoord3 {
-            x: pos.x,
-            y: pos.y,
-            z: pos.z,
-        }) {
-            let new_color = tile_color_for_kind(k);
-            sprite.color = new_color;
+    for d in &engine.engine.drones {
+        if d.position.z != ui.current_z {
+            continue;
         }
+        let color = match d.status {
+            DroneStatus::Moving => Color::srgb(0.3, 0.7, 1.0),
+            DroneStatus::Working => Color::srgb(1.0, 0.9, 0.3),
+            DroneStatus::LowPower => Color::srgb(1.0, 0.35, 0.3),
+            DroneStatus::Broken => Color::srgb(0.5, 0.2, 0.2),
+            _ => Color::srgb(0.9, 0.9, 0.9),
+        };
+        let pos = Vec3::new(
+            d.position.x as f32 * TILE_SIZE + TILE_SIZE * 0.5,
+            d.position.y as f32 * TILE_SIZE + TILE_SIZE * 0.5,
+            6.0,
+        );
+        commands.spawn((
+            Sprite {
+                color,
+                custom_size: Some(Vec2::new(TILE_SIZE * 0.7, TILE_SIZE * 0.7)),
+                ..Default::default()
+            },
+            Transform::from_translation(pos),
+            GlobalTransform::default(),
+            DroneSprite,
+        ));
     }
 }
 
@@ -363,9 +572,13 @@ fn handle_selection_input(
 }
 
 // ---------- Systems: Engine ----------
-fn tick_engine_when_running(mut eng: ResMut<GameEngine>, ui: Res<UiState>) {
-    if !ui.paused {
-        eng.engine.tick();
+fn tick_engine_when_running(mut eng: ResMut<GameEngine>, mut ui: ResMut<UiState>, time: Res<Time>) {
+    let before = eng.engine.clock.tick();
+    eng.engine.advance(time.delta_secs_f64());
+    if eng.engine.clock.tick() / AUTOSAVE_EVERY_TICKS != before / AUTOSAVE_EVERY_TICKS
+        && let Err(e) = eng.engine.save(AUTOSAVE_PATH, SaveFormat::Binary)
+    {
+        ui.console_log.push(format!("Autosave failed: {}", e));
     }
 }
 
@@ -387,6 +600,7 @@ fn draw_ui(
     mut selection: ResMut<SelectionState>,
     mut commands: Commands,
     mut q_overlay: Query<Entity, With<SelectionOverlay>>,
+    mut q_cam: Query<&mut Transform, With<Camera>>,
 ) {
     if let Ok(ctx) = egui_ctx.ctx_mut() {
         // Top HUD
@@ -394,7 +608,7 @@ fn draw_ui(
             ui_top.horizontal_wrapped(|ui_row| {
                 let hud_text = format_hud(
                     &eng.engine.world.resources,
-                    &ui.wave_label,
+                    &format_wave_label(eng.engine.next_wave(), &eng.engine.clock),
                     eng.engine.world.core_hp(),
                 );
                 ui_row.label(hud_text);
@@ -409,9 +623,96 @@ fn draw_ui(
                     ui.request_rebuild_tiles = true;
                 }
                 ui_row.separator();
-                let pause_label = if ui.paused { "Resume" } else { HUD_PAUSE_LABEL };
+                ui_row.label(format_objectives(&eng.engine.session.victory));
+                ui_row.separator();
+                if eng.engine.session_state() == SessionState::Setup
+                    && ui_row.button("Start").clicked()
+                {
+                    eng.engine.start();
+                }
+                let clock = &mut eng.engine.clock;
+                let pause_label = if clock.is_paused() {
+                    "Resume"
+                } else {
+                    HUD_PAUSE_LABEL
+                };
                 if ui_row.button(pause_label).clicked() {
-                    ui.paused = !ui.paused;
+                    clock.toggle_pause();
+                }
+                for speed in [GameSpeed::Normal, GameSpeed::Double, GameSpeed::Quadruple] {
+                    if ui_row
+                        .selectable_label(clock.speed() == speed, speed.label())
+                        .clicked()
+                    {
+                        clock.set_speed(speed);
+                    }
+                }
+                if ui_row.button("Step").clicked() {
+                    clock.step_once();
+                }
+                ui_row.label(format!("Tick {}", clock.tick()));
+                ui_row.separator();
+                if ui_row.button("Save").clicked() {
+                    match eng.engine.save(SAVE_PATH, SaveFormat::Binary) {
+                        Ok(()) => set_toast(&mut ui, format!("Saved to {}", SAVE_PATH)),
+                        Err(e) => set_toast(&mut ui, format!("Save failed: {}", e)),
+                    }
+                }
+                if ui_row.button("Load").clicked() {
+                    match Engine::load(SAVE_PATH) {
+                        Ok(mut engine) => {
+                            engine.start_recording(DEFAULT_HASH_INTERVAL);
+                            engine
+                                .enable_history(DEFAULT_SNAPSHOT_INTERVAL, DEFAULT_HISTORY_BUDGET);
+                            eng.engine = engine;
+                            ui.request_rebuild_tiles = true;
+                            set_toast(&mut ui, format!("Loaded {}", SAVE_PATH));
+                        }
+                        Err(e) => set_toast(&mut ui, format!("Load failed: {}", e)),
+                    }
+                }
+                if ui_row.button("Save replay").clicked() {
+                    let written = eng
+                        .engine
+                        .replay()
+                        .map(|r| r.write(REPLAY_PATH, SaveFormat::Binary));
+                    match written {
+                        Some(Ok(())) => {
+                            set_toast(&mut ui, format!("Replay saved to {}", REPLAY_PATH))
+                        }
+                        Some(Err(e)) => set_toast(&mut ui, format!("Replay failed: {}", e)),
+                        None => set_toast(&mut ui, "Not recording"),
+                    }
+                }
+                ui_row.separator();
+                let history = eng.engine.history();
+                let can_undo = history.is_some_and(|h| h.can_undo());
+                let can_redo = history.is_some_and(|h| h.can_redo());
+                if ui_row
+                    .add_enabled(can_undo, egui::Button::new("Undo"))
+                    .clicked()
+                {
+                    match eng.engine.undo() {
+                        Ok(_) => {
+                            // The grid may have gone back while paused
+                            ui.request_rebuild_tiles = true;
+                            set_toast(&mut ui, "Undid the last command");
+                        }
+                        Err(e) => set_toast(&mut ui, format!("Undo failed: {}", e)),
+                    }
+                }
+                if ui_row
+                    .add_enabled(can_redo, egui::Button::new("Redo"))
+                    .clicked()
+                {
+                    match eng.engine.redo() {
+                        Ok(_) => {
+                            // The grid may have gone back while paused
+                            ui.request_rebuild_tiles = true;
+                            set_toast(&mut ui, "Redid the command");
+                        }
+                        Err(e) => set_toast(&mut ui, format!("Redo failed: {}", e)),
+                    }
                 }
                 if let Some((ref msg, _)) = ui.toast {
                     ui_row.separator();
@@ -431,40 +732,121 @@ fn draw_ui(
                         let status = match d.status {
                             DroneStatus::Idle => "Idle",
                             DroneStatus::Thinking => "Thinking",
+                            DroneStatus::Moving => "Moving",
                             DroneStatus::Working => "Working",
                             DroneStatus::Finished => "Finished",
+                            DroneStatus::LowPower => "Low power",
+                            DroneStatus::Broken => "Broken",
                         };
                         let task = d
                             .current_task
                             .as_ref()
                             .map(|t| t.description())
                             .unwrap_or_else(|| "None".to_string());
-                        if ui_scroll
-                            .button(format!("Drone #{} — {} — {}", d.id, status, task))
+                        let kind = d
+                            .type_name()
+                            .map(|n| format!(" ({})", n))
+                            .unwrap_or_default();
+                        let role = d
+                            .affinity
+                            .map(|k| format!(" [{}]", k.label()))
+                            .unwrap_or_default();
+                        let mut label =
+                            format!("Drone #{}{}{} — {} — {}", d.id, kind, role, status, task);
+                        if !d.cargo.is_empty() {
+                            label.push_str(&format!(" — {}", format_cargo(&d.cargo)));
+                        }
+                        if ui_scroll.button(label).clicked()
+                            && let Ok(mut cam) = q_cam.single_mut()
+                        {
+                            cam.translation.x = d.position.x as f32 * TILE_SIZE + TILE_SIZE * 0.5;
+                            cam.translation.y = d.position.y as f32 * TILE_SIZE + TILE_SIZE * 0.5;
+                            if d.position.z != ui.current_z {
+                                ui.current_z = d.position.z;
+                                ui.request_rebuild_tiles = true;
+                            }
+                        }
+                    }
+                });
+                ui_right.separator();
+                ui_right.heading("Fabricate");
+                let mut fabricate_request = None;
+                ui_right.horizontal_wrapped(|ui_row| {
+                    for a in &eng.engine.archetypes {
+                        let affordable = eng.engine.world.resources.can_afford(&a.cost);
+                        let button = egui::Button::new(&a.name);
+                        let hint = format!(
+                            "{} stone, {} iron, {} ticks",
+                            a.cost.stone, a.cost.iron, a.build_time
+                        );
+                        if ui_row
+                            .add_enabled(affordable, button)
+                            .on_hover_text(&hint)
+                            .on_disabled_hover_text(&hint)
                             .clicked()
                         {
-                            set_toast(&mut ui, "Centering on drone is not implemented in M1");
+                            fabricate_request = Some(a.name.clone());
                         }
                     }
                 });
+                if let Some((id, left)) = eng.engine.fabrication_progress() {
+                    ui_right.label(format!("Building task #{}: {} ticks left", id, left));
+                }
+                if let Some(name) = fabricate_request {
+                    match eng.engine.apply(GameCommand::Fabricate(name.clone())) {
+                        Ok(CommandOutput::Queued(queued)) => {
+                            for (id, _) in queued {
+                                ui.console_log.push(format!(
+                                    "OK: Created task #{} (Fabricate {})",
+                                    id,
+                                    name.to_lowercase()
+                                ));
+                            }
+                        }
+                        Ok(_) => {}
+                        Err(e) => ui.console_log.push(format!("Error: {}", e)),
+                    }
+                }
                 ui_right.separator();
                 ui_right.heading("Tasks");
+                let mut cancel_request = None;
                 egui::ScrollArea::vertical().show(ui_right, |ui_scroll| {
-                    for (t, s) in &eng.engine.tasks.tasks {
+                    for (id, (t, s)) in eng.engine.tasks.tasks.iter().enumerate() {
                         let state = match s {
                             TaskState::Pending => "Pending",
                             TaskState::InProgress => "InProgress",
                             TaskState::Done => "Done",
+                            TaskState::Cancelled => "Cancelled",
                         };
-                        let label = format!("{} — {}", t.description(), state);
-                        if ui_scroll.button(label).clicked() {
-                            if let Task::MineBox(b) = t {
-                                selection.last_box = Some(*b);
+                        ui_scroll.horizontal(|ui_row| {
+                            let mut label = format!("#{} {} — {}", id, t.description(), state);
+                            if let Some(reason) = eng.engine.tasks.blocked_reason(id) {
+                                label.push_str(&format!(" ({})", reason));
+                            }
+                            if let Some(drone_id) =
+                                task_holder(&eng.engine.drones, &eng.engine.tasks, id)
+                            {
+                                label.push_str(&format!(" — Drone #{}", drone_id));
+                            }
+                            if ui_row.button(label).clicked()
+                                && let Some(b) = t.area()
+                            {
+                                selection.last_box = Some(b);
                                 set_toast(&mut ui, "Highlighted task area (visual overlay TBD)");
                             }
-                        }
+                            if matches!(s, TaskState::Pending | TaskState::InProgress)
+                                && ui_row.small_button("✖").clicked()
+                            {
+                                cancel_request = Some(id);
+                            }
+                        });
                     }
                 });
+                if let Some(id) = cancel_request
+                    && eng.engine.apply(GameCommand::CancelTask(id)).is_ok()
+                {
+                    ui.console_log.push(format!("OK: Cancelled task #{}", id));
+                }
             });
 
         // Bottom console
@@ -473,7 +855,7 @@ fn draw_ui(
             .show(&*ctx, |ui_bottom| {
                 ui_bottom.horizontal(|ui_row| {
                     let edit = egui::TextEdit::singleline(&mut ui.console_input)
-                        .hint_text("Describe task… (MVP: uses selected area to create mine_box)");
+                        .hint_text("e.g. drone 1: patrol from (20,10,0) to (25,10,0)");
                     let mut response = ui_row.add(edit);
                     if ui.focus_console {
                         response.request_focus();
@@ -484,24 +866,30 @@ fn draw_ui(
                         && response.ctx.input(|i| i.key_pressed(egui::Key::Enter));
                     if submit_clicked || enter_pressed {
                         let entered = ui.console_input.trim().to_string();
-                        if let Some(b) = selection.last_box {
-                            let program = dsl_ast_program_for_mine_box(b);
-                            match compile_program_to_tasks(&program) {
-                                Ok(tasks) => {
-                                    for t in tasks {
-                                        eng.engine.tasks.push(t);
-                                    }
-                                    ui.console_log.push(format!("> {}", entered));
-                                    ui.console_log.push("OK: Created task mine_box".to_string());
-                                    ui.console_input.clear();
-                                }
-                                Err(e) => {
-                                    ui.console_log.push(format!("> {}", entered));
-                                    ui.console_log.push(format!("Error: {}", e));
-                                }
+                        ui.console_log.push(format!("> {}", entered));
+                        let wants_stockpile = entered.to_lowercase().contains("stockpile");
+                        let parsed = parse_console_command(&entered);
+                        if let Some(b) = selection.last_box.filter(|_| wants_stockpile) {
+                            // Designating a zone is a player action, not a drone task
+                            match eng.engine.apply(GameCommand::AddStockpile(b)) {
+                                Ok(_) => ui
+                                    .console_log
+                                    .push("OK: Designated stockpile zone".to_string()),
+                                Err(e) => ui.console_log.push(format!("Error: {}", e)),
                             }
-                        } else {
-                            ui.console_log.push(format!("> {}", entered));
+                            ui.console_input.clear();
+                        } else if let Ok(cmd) = parsed {
+                            if run_console_command(&mut eng.engine, &mut ui.console_log, cmd) {
+                                ui.console_input.clear();
+                            }
+                        } else if let Some(b) = selection.last_box {
+                            let program = dsl_ast_program_for_area(area_func_for_text(&entered), b);
+                            let cmd = ConsoleCommand::Program(program);
+                            if run_console_command(&mut eng.engine, &mut ui.console_log, cmd) {
+                                ui.console_input.clear();
+                            }
+                        } else if let Err(e) = parsed {
+                            ui.console_log.push(format!("Error: {}", e));
                             ui.console_log.push(
                                 "No selection area; drag an area in Mine Area mode".to_string(),
                             );
@@ -556,7 +944,103 @@ fn draw_ui(
     } // end if Ok(ctx)
 }
 
-fn dsl_ast_program_for_mine_box(b: TileBox3) -> Program {
+// The result and run stats once the game is over, with a way to play again.
+fn draw_end_screen(
+    mut egui_ctx: EguiContexts,
+    mut ui: ResMut<UiState>,
+    mut eng: ResMut<GameEngine>,
+    mut setup: ResMut<GameSetup>,
+) {
+    let Ok(ctx) = egui_ctx.ctx_mut() else {
+        return;
+    };
+    let state = eng.engine.session_state();
+    if state.is_over() {
+        egui::Window::new(state.label())
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+            .show(&*ctx, |ui_end| {
+                let verdict = match state {
+                    SessionState::Won => "The core held. You win!",
+                    _ => "The core has fallen.",
+                };
+                ui_end.heading(verdict);
+                ui_end.separator();
+                for line in RunStats::collect(&eng.engine).to_string().lines() {
+                    ui_end.label(line);
+                }
+                ui_end.separator();
+                ui_end.horizontal(|ui_row| {
+                    let restart = ui_row.button("Restart").clicked();
+                    let reseed = ui_row.button("New seed").clicked();
+                    if reseed {
+                        setup.seed = Some(rand::random());
+                    }
+                    if restart || reseed {
+                        eng.engine = new_game(&setup);
+                        ui.request_rebuild_tiles = true;
+                        match setup.seed {
+                            Some(seed) => set_toast(&mut ui, format!("New game, seed {}", seed)),
+                            None => set_toast(&mut ui, "New game"),
+                        }
+                    }
+                });
+            });
+    }
+}
+
+// Applies a parsed console command to the engine, logging the outcome.
+fn run_console_command(engine: &mut Engine, log: &mut Vec<String>, cmd: ConsoleCommand) -> bool {
+    let drone = match &cmd {
+        ConsoleCommand::Program(program) => program.drone,
+        _ => None,
+    };
+    let done = match &cmd {
+        ConsoleCommand::Program(_) => None,
+        ConsoleCommand::Assign { task, drone } => {
+            Some(format!("OK: Assigned task #{} to drone #{}", task, drone))
+        }
+        ConsoleCommand::SetRole { drone, role } => Some(format!(
+            "OK: Drone #{} now prefers {}",
+            drone,
+            role.map(|k| k.label()).unwrap_or("any work")
+        )),
+    };
+    let queued = match engine.apply(cmd.into()) {
+        Ok(CommandOutput::Queued(queued)) => queued,
+        Ok(_) => Vec::new(),
+        Err(e) => {
+            log.push(format!("Error: {}", e));
+            return false;
+        }
+    };
+    log.extend(done);
+    for (id, conflicts) in queued {
+        let description = engine
+            .tasks
+            .get(id)
+            .map(|t| t.description())
+            .unwrap_or_default();
+        match drone {
+            Some(drone_id) => log.push(format!(
+                "OK: Created task #{} ({}) for drone #{}",
+                id, description, drone_id
+            )),
+            None => log.push(format!("OK: Created task #{} ({})", id, description)),
+        }
+        if !conflicts.is_empty() {
+            log.push(format!(
+                "WARN: Task #{} overlaps other tasks on {} tiles; it will wait",
+                id,
+                conflicts.len()
+            ));
+        }
+    }
+    true
+}
+
+fn dsl_ast_program_for_area(func: &str, b: TileBox3) -> Program {
     use serde_json::json;
     let program_json = json!({
         "version": 1,
@@ -576,7 +1060,7 @@ fn dsl_ast_program_for_mine_box(b: TileBox3) -> Program {
                 "node": "ExprStmt",
                 "expr": {
                     "node": "Call",
-                    "func": "mine_box",
+                    "func": func,
                     "args": [{ "node": "VarRef", "name": "area" }]
                 }
             }
@@ -0,0 +1,175 @@
+// Runs a game without a window, as fast as it will go, for N ticks or
+// until it is won or lost, and prints what happened. For CI and balancing
+// runs:
+//   headless [SCENARIO] [--seed N] [--load SAVE] [--script FILE]
+//            [--ticks N] [--hud-every N] [--events] [--json] [--replay FILE]
+use std::io::{self, BufWriter, Write};
+use std::process::ExitCode;
+
+use droneforge::batch::load_command_script;
+use droneforge::hud::{format_hud, format_wave_label};
+use droneforge::replay::DEFAULT_HASH_INTERVAL;
+use droneforge::*;
+use serde_json::json;
+
+const DEFAULT_SCENARIO: &str = include_str!("../../scenarios/default.json");
+const USAGE: &str = "usage: headless [SCENARIO] [--seed N] [--load SAVE] [--script FILE] \
+                     [--ticks N] [--hud-every N] [--events] [--json] [--replay FILE]";
+
+struct Options {
+    scenario: Option<String>,
+    seed: Option<u64>,
+    load: Option<String>,
+    script: Option<String>,
+    ticks: u64,
+    hud_every: u64,
+    events: bool,
+    json: bool,
+    replay: Option<String>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        scenario: None,
+        seed: None,
+        load: None,
+        script: None,
+        ticks: 1000,
+        hud_every: 100,
+        events: false,
+        json: false,
+        replay: None,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or(format!("{} needs a value", name));
+        let number = |name: &str, v: String| {
+            v.parse::<u64>()
+                .map_err(|_| format!("{} needs a number, got {}", name, v))
+        };
+        match arg.as_str() {
+            "--seed" => options.seed = Some(number("--seed", value("--seed")?)?),
+            "--load" => options.load = Some(value("--load")?),
+            "--script" => options.script = Some(value("--script")?),
+            "--ticks" => options.ticks = number("--ticks", value("--ticks")?)?,
+            "--hud-every" => options.hud_every = number("--hud-every", value("--hud-every")?)?,
+            "--events" => options.events = true,
+            "--json" => options.json = true,
+            "--replay" => options.replay = Some(value("--replay")?),
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            flag if flag.starts_with('-') => return Err(format!("Unknown option {}", flag)),
+            _ if options.scenario.is_none() => options.scenario = Some(arg),
+            _ => return Err(format!("Unexpected argument {}", arg)),
+        }
+    }
+    Ok(options)
+}
+
+// A saved game, or the scenario (the bundled one by default) with its
+// generated map and breakdowns reseeded when a seed is given.
+fn starting_engine(options: &Options) -> Result<Engine, String> {
+    if let Some(path) = &options.load {
+        return Engine::load(path).map_err(|e| format!("Couldn't load {}: {}", path, e));
+    }
+    let scenario = match &options.scenario {
+        Some(path) => Scenario::load(path).map_err(|e| format!("{}: {}", path, e))?,
+        None => Scenario::from_json(DEFAULT_SCENARIO).expect("bundled scenario is valid"),
+    };
+    match options.seed {
+        Some(seed) => scenario.build_with_seed(seed),
+        None => scenario.build(),
+    }
+    .map_err(|e| format!("Scenario {}: {}", scenario.name, e))
+}
+
+fn run(options: Options) -> Result<(), String> {
+    let mut engine = starting_engine(&options)?;
+    let commands = match &options.script {
+        Some(path) => load_command_script(path).map_err(|e| format!("{}: {}", path, e))?,
+        None => Vec::new(),
+    };
+    if options.replay.is_some() {
+        engine.start_recording(DEFAULT_HASH_INTERVAL);
+    }
+    let mut out = BufWriter::new(io::stdout().lock());
+    match simulate(&options, &mut engine, commands, &mut out) {
+        Ok(()) => {}
+        // Whoever was reading has gone, e.g. `| head`
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+        Err(e) => return Err(e.to_string()),
+    }
+    if let (Some(path), Some(replay)) = (&options.replay, engine.stop_recording()) {
+        replay
+            .write(path, SaveFormat::Binary)
+            .map_err(|e| format!("Couldn't write replay {}: {}", path, e))?;
+    }
+    Ok(())
+}
+
+// Runs the ticks, giving each command at its tick (counted from the start
+// of this run), and writes out what happened.
+fn simulate(
+    options: &Options,
+    engine: &mut Engine,
+    commands: Vec<CommandRecord>,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let start = engine.clock.tick();
+    let end = start + options.ticks;
+    let mut commands = commands.into_iter().peekable();
+    loop {
+        let tick = engine.clock.tick();
+        while let Some(record) = commands.next_if(|r| start + r.tick <= tick) {
+            if let Err(e) = engine.apply(record.command) {
+                if options.json {
+                    writeln!(out, "{}", json!({ "tick": tick, "error": e.to_string() }))?;
+                } else {
+                    eprintln!("[tick {}] Command failed: {}", tick, e);
+                }
+            }
+        }
+        if tick >= end || engine.session_state().is_over() {
+            break;
+        }
+        engine.tick();
+        let tick = engine.clock.tick();
+
+        let events = engine.drain_events();
+        if options.events {
+            for event in events {
+                if options.json {
+                    writeln!(out, "{}", json!({ "tick": tick, "event": event }))?;
+                } else {
+                    writeln!(out, "[tick {}] {:?}", tick, event)?;
+                }
+            }
+        }
+        if options.hud_every > 0 && (tick - start).is_multiple_of(options.hud_every) {
+            let wave = format_wave_label(engine.next_wave(), &engine.clock);
+            let hud = format_hud(&engine.world.resources, &wave, engine.world.core_hp());
+            if options.json {
+                writeln!(out, "{}", json!({ "tick": tick, "hud": hud }))?;
+            } else {
+                writeln!(out, "[tick {}] {}", tick, hud)?;
+            }
+        }
+    }
+
+    let stats = RunStats::collect(engine);
+    if options.json {
+        writeln!(out, "{}", json!({ "stats": stats }))?;
+    } else {
+        writeln!(out, "{}", stats)?;
+    }
+    out.flush()
+}
+
+fn main() -> ExitCode {
+    let result = parse_args(std::env::args().skip(1)).and_then(run);
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+// A value drone programs can store on the blackboard or send each other.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Value {
+    Int(i64),
+    Text(String),
+    Flag(bool),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(n) => write!(f, "{}", n),
+            Value::Text(s) => write!(f, "\"{}\"", s),
+            Value::Flag(b) => write!(f, "{}", b),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Message {
+    // Sending drone, if the program was bound to one
+    pub from: Option<u32>,
+    pub value: Value,
+}
+
+// Shared state for cooperating drone programs: named values every program
+// can see, plus one message queue per drone, read oldest first.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Blackboard {
+    values: BTreeMap<String, Value>,
+    inboxes: BTreeMap<u32, VecDeque<Message>>,
+}
+
+impl Blackboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.values.get(key)
+    }
+
+    pub fn set(&mut self, key: impl Into<String>, value: Value) {
+        self.values.insert(key.into(), value);
+    }
+
+    pub fn set_flag(&mut self, name: impl Into<String>) {
+        self.set(name, Value::Flag(true));
+    }
+
+    // Any value other than a false flag counts as set.
+    pub fn is_set(&self, name: &str) -> bool {
+        matches!(self.get(name), Some(v) if *v != Value::Flag(false))
+    }
+
+    pub fn send(&mut self, to: u32, from: Option<u32>, value: Value) {
+        self.inboxes
+            .entry(to)
+            .or_default()
+            .push_back(Message { from, value });
+    }
+
+    pub fn recv(&mut self, drone: u32) -> Option<Message> {
+        self.inboxes.get_mut(&drone)?.pop_front()
+    }
+
+    pub fn pending_messages(&self, drone: u32) -> usize {
+        self.inboxes.get(&drone).map_or(0, VecDeque::len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_and_messages() {
+        let mut board = Blackboard::new();
+        assert!(!board.is_set("shaft_done"));
+        board.set_flag("shaft_done");
+        assert!(board.is_set("shaft_done"));
+        board.set("depth", Value::Int(3));
+        assert_eq!(board.get("depth"), Some(&Value::Int(3)));
+
+        board.send(2, Some(1), Value::Text("go".into()));
+        board.send(2, None, Value::Int(7));
+        assert_eq!(board.pending_messages(2), 2);
+        assert_eq!(board.recv(2).unwrap().from, Some(1));
+        assert_eq!(board.recv(2).unwrap().value, Value::Int(7));
+        assert!(board.recv(2).is_none());
+        assert!(board.recv(5).is_none());
+    }
+}
@@ -0,0 +1,367 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+
+use crate::coords::TileCoord3;
+use crate::pathfinding::{Path, find_path, neighbors};
+use crate::tile::TileKind;
+use crate::world::World;
+
+pub const CHUNK_SIZE: i32 = 16;
+
+// Chunk column, row and level.
+type ChunkKey = (i32, i32, i32);
+
+// Coarse graph for long drone trips. The map is cut into square chunks per
+// level; each open stretch of a chunk border becomes one entrance, stairs
+// link levels, and the entrances of a chunk are joined by their walking
+// distance inside it. A query searches this small graph and only refines
+// the short legs between entrances with plain A*.
+#[derive(Debug, Clone)]
+pub struct ChunkGraph {
+    revision: u64,
+    // Crossing tile pairs on each shared border, keyed by the ordered pair
+    // of chunks; the first tile of a pair lies in the first chunk
+    borders: HashMap<(ChunkKey, ChunkKey), Vec<(TileCoord3, TileCoord3)>>,
+    // Walking distances between the entrances of one chunk
+    inner: HashMap<ChunkKey, HashMap<TileCoord3, Vec<(TileCoord3, u32)>>>,
+}
+
+impl ChunkGraph {
+    pub fn new(world: &World) -> Self {
+        let mut graph = Self {
+            revision: world.revision(),
+            borders: HashMap::new(),
+            inner: HashMap::new(),
+        };
+        graph.rebuild(world);
+        graph
+    }
+
+    pub fn entrance_count(&self) -> usize {
+        self.inner.values().map(HashMap::len).sum()
+    }
+
+    pub fn rebuild(&mut self, world: &World) {
+        self.borders.clear();
+        self.inner.clear();
+        let chunks: Vec<_> = all_chunks(world).collect();
+        for k in &chunks {
+            self.rebuild_borders(world, *k);
+        }
+        for k in &chunks {
+            self.rebuild_inner(world, *k);
+        }
+        self.revision = world.revision();
+    }
+
+    // Re-cuts only the chunks that had tiles change since the last update,
+    // plus the inner edges of their neighbours.
+    pub fn update(&mut self, world: &World) {
+        if self.revision == world.revision() {
+            return;
+        }
+        let Some(changes) = world.changes_since(self.revision) else {
+            self.rebuild(world);
+            return;
+        };
+        let dirty: HashSet<ChunkKey> = changes.iter().map(|c| chunk_of(*c)).collect();
+        let mut touched = HashSet::new();
+        for k in &dirty {
+            self.rebuild_borders(world, *k);
+            touched.insert(*k);
+            touched.extend(neighbor_chunks(world, *k));
+        }
+        for k in touched {
+            self.rebuild_inner(world, k);
+        }
+        self.revision = world.revision();
+    }
+
+    // Long-range path: abstract search over entrances, then A* between
+    // consecutive waypoints. Falls back to plain A* for short trips.
+    pub fn find_path(&self, world: &World, start: TileCoord3, goal: TileCoord3) -> Option<Path> {
+        let (start_chunk, goal_chunk) = (chunk_of(start), chunk_of(goal));
+        if start_chunk == goal_chunk || !world.is_passable(goal) {
+            return find_path(world, start, goal);
+        }
+        let from_start = chunk_distances(world, start);
+        let to_goal = chunk_distances(world, goal);
+        let entrances = |k: ChunkKey| self.inner.get(&k).into_iter().flat_map(|m| m.keys());
+        let starts: Vec<_> = entrances(start_chunk)
+            .filter_map(|e| Some((*e, *from_start.get(e)?)))
+            .collect();
+        let finishes: HashMap<TileCoord3, u32> = entrances(goal_chunk)
+            .filter_map(|e| Some((*e, *to_goal.get(e)?)))
+            .collect();
+
+        let mut open = BinaryHeap::new();
+        let mut cost: HashMap<TileCoord3, u32> = HashMap::new();
+        let mut came_from: HashMap<TileCoord3, TileCoord3> = HashMap::new();
+        for (e, d) in starts {
+            if cost.get(&e).is_none_or(|best| d < *best) {
+                cost.insert(e, d);
+                open.push(Reverse((d + e.manhattan(goal) as u32, d, key(e))));
+            }
+        }
+        let mut best: Option<(u32, TileCoord3)> = None;
+        while let Some(Reverse((f, g, (z, y, x)))) = open.pop() {
+            if best.is_some_and(|(b, _)| f >= b) {
+                break;
+            }
+            let e = TileCoord3::new(x, y, z);
+            if cost.get(&e).is_some_and(|c| g > *c) {
+                continue;
+            }
+            if let Some(rest) = finishes.get(&e)
+                && best.is_none_or(|(b, _)| g + rest < b)
+            {
+                best = Some((g + rest, e));
+            }
+            for (n, step) in self.edges(e) {
+                let next = g + step;
+                if cost.get(&n).is_some_and(|c| next >= *c) {
+                    continue;
+                }
+                cost.insert(n, next);
+                came_from.insert(n, e);
+                open.push(Reverse((next + n.manhattan(goal) as u32, next, key(n))));
+            }
+        }
+        let (_, last) = best?;
+        let mut waypoints = vec![goal, last];
+        let mut at = last;
+        while let Some(prev) = came_from.get(&at) {
+            waypoints.push(*prev);
+            at = *prev;
+        }
+        waypoints.push(start);
+        waypoints.reverse();
+
+        let mut tiles = vec![start];
+        for leg in waypoints.windows(2) {
+            let part = find_path(world, leg[0], leg[1])?;
+            tiles.extend_from_slice(&part.tiles[1..]);
+        }
+        Some(Path { tiles })
+    }
+
+    fn edges(&self, e: TileCoord3) -> Vec<(TileCoord3, u32)> {
+        let k = chunk_of(e);
+        let mut out: Vec<_> = self
+            .inner
+            .get(&k)
+            .and_then(|m| m.get(&e))
+            .cloned()
+            .unwrap_or_default();
+        out.extend(
+            self.crossings(k)
+                .filter(|(mine, _)| *mine == e)
+                .map(|(_, other)| (other, 1)),
+        );
+        out
+    }
+
+    // Crossings out of chunk `k`, as (tile in `k`, tile across the border).
+    fn crossings(&self, k: ChunkKey) -> impl Iterator<Item = (TileCoord3, TileCoord3)> + '_ {
+        let (x, y, z) = k;
+        let sides = [
+            (x + 1, y, z),
+            (x - 1, y, z),
+            (x, y + 1, z),
+            (x, y - 1, z),
+            (x, y, z + 1),
+            (x, y, z - 1),
+        ];
+        sides.into_iter().flat_map(move |n| {
+            let pair = if k < n { (k, n) } else { (n, k) };
+            self.borders
+                .get(&pair)
+                .into_iter()
+                .flatten()
+                .map(move |(p, q)| if pair.0 == k { (*p, *q) } else { (*q, *p) })
+        })
+    }
+
+    fn rebuild_borders(&mut self, world: &World, k: ChunkKey) {
+        for n in neighbor_chunks(world, k) {
+            let pair = if k < n { (k, n) } else { (n, k) };
+            let crossings = border_crossings(world, pair.0, pair.1);
+            if crossings.is_empty() {
+                self.borders.remove(&pair);
+            } else {
+                self.borders.insert(pair, crossings);
+            }
+        }
+    }
+
+    fn rebuild_inner(&mut self, world: &World, k: ChunkKey) {
+        let entrances: HashSet<_> = self.crossings(k).map(|(mine, _)| mine).collect();
+        let mut edges = HashMap::new();
+        for e in &entrances {
+            let dist = chunk_distances(world, *e);
+            let reachable = entrances
+                .iter()
+                .filter(|o| *o != e)
+                .filter_map(|o| Some((*o, *dist.get(o)?)))
+                .collect();
+            edges.insert(*e, reachable);
+        }
+        if edges.is_empty() {
+            self.inner.remove(&k);
+        } else {
+            self.inner.insert(k, edges);
+        }
+    }
+}
+
+fn key(c: TileCoord3) -> (i32, i32, i32) {
+    (c.z, c.y, c.x)
+}
+
+fn chunk_of(c: TileCoord3) -> ChunkKey {
+    (c.x.div_euclid(CHUNK_SIZE), c.y.div_euclid(CHUNK_SIZE), c.z)
+}
+
+fn all_chunks(world: &World) -> impl Iterator<Item = ChunkKey> {
+    let cols = (world.width() + CHUNK_SIZE - 1) / CHUNK_SIZE;
+    let rows = (world.height() + CHUNK_SIZE - 1) / CHUNK_SIZE;
+    let levels = world.levels();
+    (0..levels).flat_map(move |z| (0..rows).flat_map(move |y| (0..cols).map(move |x| (x, y, z))))
+}
+
+fn neighbor_chunks(world: &World, k: ChunkKey) -> Vec<ChunkKey> {
+    let (x, y, z) = k;
+    let cols = (world.width() + CHUNK_SIZE - 1) / CHUNK_SIZE;
+    let rows = (world.height() + CHUNK_SIZE - 1) / CHUNK_SIZE;
+    [
+        (x + 1, y, z),
+        (x - 1, y, z),
+        (x, y + 1, z),
+        (x, y - 1, z),
+        (x, y, z + 1),
+        (x, y, z - 1),
+    ]
+    .into_iter()
+    .filter(|(x, y, z)| {
+        (0..cols).contains(x) && (0..rows).contains(y) && (0..world.levels()).contains(z)
+    })
+    .collect()
+}
+
+// Tiles of `a` that step straight into `b`: one pair from the middle of
+// each open stretch of a side border, and every stairs pair between levels.
+fn border_crossings(world: &World, a: ChunkKey, b: ChunkKey) -> Vec<(TileCoord3, TileCoord3)> {
+    let (ax, ay, az) = a;
+    if az != b.2 {
+        let mut out = Vec::new();
+        for y in ay * CHUNK_SIZE..(ay + 1) * CHUNK_SIZE {
+            for x in ax * CHUNK_SIZE..(ax + 1) * CHUNK_SIZE {
+                let p = TileCoord3::new(x, y, az);
+                let q = TileCoord3::new(x, y, b.2);
+                if world.get_tile(p) == Some(TileKind::Stairs)
+                    && world.get_tile(q) == Some(TileKind::Stairs)
+                {
+                    out.push((p, q));
+                }
+            }
+        }
+        return out;
+    }
+    // Walk along the shared edge, from `a`'s last column/row into `b`'s first
+    let side: Vec<(TileCoord3, TileCoord3)> = if b.0 > ax {
+        let x = (ax + 1) * CHUNK_SIZE - 1;
+        (ay * CHUNK_SIZE..(ay + 1) * CHUNK_SIZE)
+            .map(|y| (TileCoord3::new(x, y, az), TileCoord3::new(x + 1, y, az)))
+            .collect()
+    } else {
+        let y = (ay + 1) * CHUNK_SIZE - 1;
+        (ax * CHUNK_SIZE..(ax + 1) * CHUNK_SIZE)
+            .map(|x| (TileCoord3::new(x, y, az), TileCoord3::new(x, y + 1, az)))
+            .collect()
+    };
+    let mut out = Vec::new();
+    let mut run = Vec::new();
+    for (p, q) in side {
+        if world.is_passable(p) && world.is_passable(q) {
+            run.push((p, q));
+            continue;
+        }
+        if !run.is_empty() {
+            out.push(run[run.len() / 2]);
+            run.clear();
+        }
+    }
+    if !run.is_empty() {
+        out.push(run[run.len() / 2]);
+    }
+    out
+}
+
+// Walking distance from `from` to every tile of its own chunk it can reach
+// without leaving the chunk.
+fn chunk_distances(world: &World, from: TileCoord3) -> HashMap<TileCoord3, u32> {
+    let k = chunk_of(from);
+    let mut dist = HashMap::from([(from, 0)]);
+    let mut queue = VecDeque::from([from]);
+    while let Some(c) = queue.pop_front() {
+        let d = dist[&c];
+        for n in neighbors(world, c) {
+            if chunk_of(n) == k && !dist.contains_key(&n) {
+                dist.insert(n, d + 1);
+                queue.push_back(n);
+            }
+        }
+    }
+    dist
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Open 64x64 map with a long wall that has a single gap at the top.
+    fn walled_world() -> World {
+        let mut world = World::new(64, 64, 1, TileKind::Air);
+        for y in 0..60 {
+            world.set_tile(TileCoord3::new(32, y, 0), TileKind::Wall);
+        }
+        world
+    }
+
+    #[test]
+    fn long_paths_are_valid_and_near_optimal() {
+        let world = walled_world();
+        let graph = ChunkGraph::new(&world);
+        assert!(graph.entrance_count() > 0);
+        let start = TileCoord3::new(2, 2, 0);
+        let goal = TileCoord3::new(60, 2, 0);
+        let path = graph.find_path(&world, start, goal).unwrap();
+        assert_eq!((path.start(), path.end()), (start, goal));
+        for pair in path.tiles.windows(2) {
+            assert_eq!(pair[0].manhattan(pair[1]), 1);
+            assert!(world.is_passable(pair[1]));
+        }
+        let best = find_path(&world, start, goal).unwrap();
+        // Entrances sit mid-border, so the detour is bounded by a chunk or so
+        assert!(path.len() <= best.len() + 2 * CHUNK_SIZE as usize);
+    }
+
+    #[test]
+    fn update_follows_world_changes() {
+        let mut world = walled_world();
+        let mut graph = ChunkGraph::new(&world);
+        let start = TileCoord3::new(2, 2, 0);
+        let goal = TileCoord3::new(60, 2, 0);
+        // Seal the gap
+        for y in 60..64 {
+            world.set_tile(TileCoord3::new(32, y, 0), TileKind::Wall);
+        }
+        graph.update(&world);
+        assert!(graph.find_path(&world, start, goal).is_none());
+        // Open a door low down
+        world.set_tile(TileCoord3::new(32, 5, 0), TileKind::Floor);
+        graph.update(&world);
+        let path = graph.find_path(&world, start, goal).unwrap();
+        assert!(path.tiles.contains(&TileCoord3::new(32, 5, 0)));
+    }
+}
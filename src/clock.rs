@@ -0,0 +1,155 @@
+use serde::{Deserialize, Serialize};
+
+// Simulation ticks per second at normal speed.
+pub const DEFAULT_TICK_RATE: u32 = 10;
+// Most ticks one `advance` will run; after a long stall the clock drops
+// the backlog instead of freezing the frame to catch up.
+pub const MAX_TICKS_PER_ADVANCE: u32 = 16;
+
+const MICROS_PER_SECOND: u64 = 1_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameSpeed {
+    Paused,
+    Normal,
+    Double,
+    Quadruple,
+}
+
+impl GameSpeed {
+    pub fn multiplier(self) -> u64 {
+        match self {
+            GameSpeed::Paused => 0,
+            GameSpeed::Normal => 1,
+            GameSpeed::Double => 2,
+            GameSpeed::Quadruple => 4,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            GameSpeed::Paused => "Paused",
+            GameSpeed::Normal => "1x",
+            GameSpeed::Double => "2x",
+            GameSpeed::Quadruple => "4x",
+        }
+    }
+}
+
+// Fixed-timestep clock: real time goes in through `advance`, whole ticks
+// come out, so the simulation runs at the same rate whatever the frame
+// rate. Time is kept in integer microseconds so stepping never drifts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimClock {
+    tick_rate: u32,
+    tick: u64,
+    speed: GameSpeed,
+    // Speed to go back to when unpausing
+    resume_speed: GameSpeed,
+    accumulated: u64,
+    // Single steps asked for while paused
+    pending_steps: u32,
+}
+
+impl Default for SimClock {
+    fn default() -> Self {
+        Self::new(DEFAULT_TICK_RATE)
+    }
+}
+
+impl SimClock {
+    pub fn new(tick_rate: u32) -> Self {
+        Self {
+            tick_rate: tick_rate.max(1),
+            tick: 0,
+            speed: GameSpeed::Normal,
+            resume_speed: GameSpeed::Normal,
+            accumulated: 0,
+            pending_steps: 0,
+        }
+    }
+
+    // Ticks run so far.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub fn tick_rate(&self) -> u32 {
+        self.tick_rate
+    }
+
+    // Simulated seconds per tick.
+    pub fn tick_length(&self) -> f64 {
+        1.0 / self.tick_rate as f64
+    }
+
+    pub fn speed(&self) -> GameSpeed {
+        self.speed
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.speed == GameSpeed::Paused
+    }
+
+    pub fn set_speed(&mut self, speed: GameSpeed) {
+        if speed != GameSpeed::Paused {
+            self.resume_speed = speed;
+        }
+        self.speed = speed;
+        self.accumulated = 0;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        if self.is_paused() {
+            self.set_speed(self.resume_speed);
+        } else {
+            self.set_speed(GameSpeed::Paused);
+        }
+    }
+
+    // Runs exactly one more tick on the next `advance`, paused or not.
+    pub fn step_once(&mut self) {
+        self.pending_steps += 1;
+    }
+
+    // Adds `seconds` of real time and returns how many ticks are now due.
+    pub fn advance(&mut self, seconds: f64) -> u32 {
+        let micros = (seconds.max(0.0) * MICROS_PER_SECOND as f64).round() as u64;
+        self.accumulated += micros * self.speed.multiplier();
+        let per_tick = MICROS_PER_SECOND / self.tick_rate as u64;
+        let due = self.accumulated / per_tick;
+        self.accumulated %= per_tick;
+        let steps = std::mem::take(&mut self.pending_steps);
+        (due.min(MAX_TICKS_PER_ADVANCE as u64) as u32).saturating_add(steps)
+    }
+
+    // Counts a tick the engine has just run.
+    pub fn record_tick(&mut self) {
+        self.tick += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ticks_follow_real_time_and_speed() {
+        let mut clock = SimClock::new(10);
+        // Sixty frames of a second in total come out as ten ticks
+        let ticks: u32 = (0..60).map(|_| clock.advance(1.0 / 60.0)).sum();
+        assert_eq!(ticks, 10);
+        clock.set_speed(GameSpeed::Quadruple);
+        assert_eq!(clock.advance(0.5), 16);
+        // A long stall doesn't pile up ticks
+        assert_eq!(clock.advance(10.0), MAX_TICKS_PER_ADVANCE);
+
+        clock.toggle_pause();
+        assert!(clock.is_paused());
+        assert_eq!(clock.advance(1.0), 0);
+        clock.step_once();
+        assert_eq!(clock.advance(0.0), 1);
+        clock.toggle_pause();
+        assert_eq!(clock.speed(), GameSpeed::Quadruple);
+    }
+}
@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::console::ConsoleCommand;
+use crate::coords::TileBox3;
+use crate::dsl_ast::{CompileError, Program};
+use crate::engine::AssignError;
+use crate::reservations::ReservationConflict;
+use crate::script::ScriptId;
+use crate::tasks::{Task, TaskId, TaskKind};
+
+// A player input. Everything the player does to a running game goes through
+// `Engine::apply` as one of these, so a game can be replayed from its
+// starting state and the list of commands.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Command {
+    QueueTask { task: Task, drone: Option<u32> },
+    QueueProgram(Program),
+    RunProgram(Program),
+    CancelTask(TaskId),
+    AssignTask { task: TaskId, drone: u32 },
+    SetRole { drone: u32, role: Option<TaskKind> },
+    Fabricate(String),
+    AddStockpile(TileBox3),
+}
+
+// A command and the tick it was applied at: after that many ticks had run,
+// before the next one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandRecord {
+    pub tick: u64,
+    pub command: Command,
+}
+
+#[derive(Debug)]
+pub enum CommandOutput {
+    Queued(Vec<(TaskId, Vec<ReservationConflict>)>),
+    Started(ScriptId),
+    Done,
+}
+
+#[derive(Debug, Error)]
+pub enum CommandError {
+    #[error(transparent)]
+    Compile(#[from] CompileError),
+    #[error(transparent)]
+    Assign(#[from] AssignError),
+    #[error("Task #{0} is already finished")]
+    NotCancellable(TaskId),
+}
+
+impl From<ConsoleCommand> for Command {
+    fn from(cmd: ConsoleCommand) -> Self {
+        match cmd {
+            ConsoleCommand::Program(program) => Command::QueueProgram(program),
+            ConsoleCommand::Assign { task, drone } => Command::AssignTask { task, drone },
+            ConsoleCommand::SetRole { drone, role } => Command::SetRole { drone, role },
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use thiserror::Error;
+
+use crate::coords::TileCoord3;
+use crate::dsl_ast::{Coord, Expr, Program, Statement};
+use crate::tasks::{TaskId, TaskKind};
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ConsoleError {
+    #[error("Unrecognized command")]
+    UnknownCommand,
+    #[error("Invalid drone id: {0}")]
+    InvalidDrone(String),
+    #[error("Invalid coordinate: {0}")]
+    InvalidCoord(String),
+    #[error("{0} needs {1}")]
+    WrongArgs(&'static str, &'static str),
+    #[error("Unknown role: {0}")]
+    UnknownRole(String),
+}
+
+#[derive(Debug, Clone)]
+pub enum ConsoleCommand {
+    // Lowered to the same AST the LLM would produce; `Program::drone` is set
+    // when the line was addressed to one drone
+    Program(Program),
+    Assign { task: TaskId, drone: u32 },
+    SetRole { drone: u32, role: Option<TaskKind> },
+}
+
+// Stand-in for the LLM step: pick the area host function from keywords.
+pub fn area_func_for_text(text: &str) -> &'static str {
+    let text = text.to_lowercase();
+    if text.contains("wall") && text.contains("border") {
+        "build_wall_on_border"
+    } else if text.contains("wall") {
+        "build_wall"
+    } else if text.contains("floor") {
+        "build_floor"
+    } else if text.contains("charger") {
+        "build_charger"
+    } else if text.contains("haul") {
+        "haul"
+    } else if text.contains("guard") {
+        "guard"
+    } else {
+        "mine_box"
+    }
+}
+
+// Parses lines such as
+//   drone 3: patrol from (20,10,0) to (25,10,0)
+//   move to (4,4)
+//   mine (0,0,0) to (5,5,0)
+//   assign task #4 to drone #2
+//   drone 2: role miner
+//   fabricate scout
+//   repair drone #3
+// Coordinates default to z = 0 when only x and y are given.
+pub fn parse_console_command(input: &str) -> Result<ConsoleCommand, ConsoleError> {
+    let (drone, rest) = split_drone_prefix(input.trim())?;
+    let verb = rest.to_lowercase();
+
+    if verb.starts_with("assign") {
+        let ids: Vec<u32> = verb
+            .split_whitespace()
+            .filter_map(|w| w.trim_start_matches('#').parse().ok())
+            .collect();
+        let [task, drone] = ids[..] else {
+            return Err(ConsoleError::WrongArgs("assign", "a task and a drone id"));
+        };
+        return Ok(ConsoleCommand::Assign {
+            task: task as TaskId,
+            drone,
+        });
+    }
+    if let Some(role) = verb.strip_prefix("role") {
+        let Some(drone) = drone else {
+            return Err(ConsoleError::WrongArgs("role", "a \"drone N:\" prefix"));
+        };
+        let role = match role.trim() {
+            "none" | "any" => None,
+            name => Some(
+                TaskKind::from_name(name)
+                    .ok_or_else(|| ConsoleError::UnknownRole(name.to_string()))?,
+            ),
+        };
+        return Ok(ConsoleCommand::SetRole { drone, role });
+    }
+
+    let coords = parse_coords(rest)?;
+    let call = if let Some(kind) = verb
+        .strip_prefix("fabricate")
+        .or_else(|| verb.strip_prefix("fab"))
+    {
+        let value = kind.trim().to_string();
+        if value.is_empty() {
+            return Err(ConsoleError::WrongArgs("fabricate", "a drone type"));
+        }
+        call("fabricate", vec![Expr::StringLiteral { value }])
+    } else if verb.starts_with("repair") {
+        let ids: Vec<i64> = verb
+            .split_whitespace()
+            .filter_map(|w| w.trim_start_matches('#').parse().ok())
+            .collect();
+        let [value] = ids[..] else {
+            return Err(ConsoleError::WrongArgs("repair", "a drone id"));
+        };
+        call("repair", vec![Expr::IntLiteral { value }])
+    } else if verb.starts_with("patrol") {
+        if coords.len() < 2 {
+            return Err(ConsoleError::WrongArgs("patrol", "at least two waypoints"));
+        }
+        call("patrol", coords.iter().map(|c| coord_expr(*c)).collect())
+    } else if verb.starts_with("move") || verb.starts_with("go") {
+        if coords.len() != 1 {
+            return Err(ConsoleError::WrongArgs("move", "one destination"));
+        }
+        call("move_to", vec![coord_expr(coords[0])])
+    } else if ["mine", "build", "haul", "guard", "wall", "floor"]
+        .iter()
+        .any(|v| verb.starts_with(v))
+    {
+        if coords.len() != 2 {
+            return Err(ConsoleError::WrongArgs("area commands", "two corners"));
+        }
+        let func = area_func_for_text(&verb);
+        if verb.starts_with("build") && func == "mine_box" {
+            return Err(ConsoleError::WrongArgs(
+                "build",
+                "wall, floor, border or charger",
+            ));
+        }
+        call(func, vec![box_expr(coords[0], coords[1])])
+    } else {
+        return Err(ConsoleError::UnknownCommand);
+    };
+
+    Ok(ConsoleCommand::Program(Program {
+        version: 1,
+        node: "Program".to_string(),
+        drone,
+        statements: vec![Statement::ExprStmt { expr: call }],
+    }))
+}
+
+fn split_drone_prefix(input: &str) -> Result<(Option<u32>, &str), ConsoleError> {
+    let lower = input.to_lowercase();
+    if !lower.starts_with("drone") {
+        return Ok((None, input));
+    }
+    let Some((head, rest)) = input.split_once(':') else {
+        return Ok((None, input));
+    };
+    let id_text = head["drone".len()..].trim().trim_start_matches('#');
+    let id = id_text
+        .parse()
+        .map_err(|_| ConsoleError::InvalidDrone(id_text.to_string()))?;
+    Ok((Some(id), rest.trim()))
+}
+
+fn parse_coords(text: &str) -> Result<Vec<TileCoord3>, ConsoleError> {
+    let mut coords = Vec::new();
+    let mut rest = text;
+    while let Some(open) = rest.find('(') {
+        let Some(close) = rest[open..].find(')') else {
+            return Err(ConsoleError::InvalidCoord(rest[open..].to_string()));
+        };
+        let inner = &rest[open + 1..open + close];
+        let parts: Result<Vec<i32>, _> = inner.split(',').map(|p| p.trim().parse()).collect();
+        match parts.as_deref() {
+            Ok([x, y]) => coords.push(TileCoord3::new(*x, *y, 0)),
+            Ok([x, y, z]) => coords.push(TileCoord3::new(*x, *y, *z)),
+            _ => return Err(ConsoleError::InvalidCoord(inner.to_string())),
+        }
+        rest = &rest[open + close + 1..];
+    }
+    Ok(coords)
+}
+
+fn call(func: &str, args: Vec<Expr>) -> Expr {
+    Expr::Call {
+        func: func.to_string(),
+        args,
+    }
+}
+
+fn coord_expr(c: TileCoord3) -> Expr {
+    Expr::TileCoord {
+        x: c.x,
+        y: c.y,
+        z: c.z,
+    }
+}
+
+// Corners may be given in any order; the AST wants min/max.
+fn box_expr(a: TileCoord3, b: TileCoord3) -> Expr {
+    let corner = |x, y, z| Coord {
+        node: "TileCoord".to_string(),
+        x,
+        y,
+        z,
+    };
+    Expr::TileBoxFromCoords {
+        min: corner(a.x.min(b.x), a.y.min(b.y), a.z.min(b.z)),
+        max: corner(a.x.max(b.x), a.y.max(b.y), a.z.max(b.z)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsl_ast::compile_program_to_tasks;
+    use crate::tasks::Task;
+
+    fn program(input: &str) -> Program {
+        match parse_console_command(input).unwrap() {
+            ConsoleCommand::Program(p) => p,
+            other => panic!("expected a program, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn design_patrol_example() {
+        let p = program("drone 3: patrol from (20,10,0) to (25,10,0)");
+        assert_eq!(p.drone, Some(3));
+        let tasks = compile_program_to_tasks(&p).unwrap();
+        assert_eq!(
+            tasks,
+            vec![Task::Patrol(vec![
+                TileCoord3::new(20, 10, 0),
+                TileCoord3::new(25, 10, 0)
+            ])]
+        );
+    }
+
+    #[test]
+    fn unaddressed_area_command_normalizes_corners() {
+        let p = program("mine (5,5) to (0,0)");
+        assert_eq!(p.drone, None);
+        let tasks = compile_program_to_tasks(&p).unwrap();
+        assert!(matches!(&tasks[0], Task::MineBox(b) if b.min == TileCoord3::new(0, 0, 0)));
+    }
+
+    #[test]
+    fn assign_and_role_commands() {
+        assert!(matches!(
+            parse_console_command("assign task #4 to drone #2"),
+            Ok(ConsoleCommand::Assign { task: 4, drone: 2 })
+        ));
+        assert!(matches!(
+            parse_console_command("drone 2: role miner"),
+            Ok(ConsoleCommand::SetRole {
+                drone: 2,
+                role: Some(TaskKind::Mine)
+            })
+        ));
+        assert!(matches!(
+            parse_console_command("drone 2: role none"),
+            Ok(ConsoleCommand::SetRole {
+                drone: 2,
+                role: None
+            })
+        ));
+        assert!(parse_console_command("role miner").is_err());
+    }
+
+    #[test]
+    fn fabricate_command() {
+        let tasks = compile_program_to_tasks(&program("fabricate Scout")).unwrap();
+        assert_eq!(tasks, vec![Task::Fabricate("scout".to_string())]);
+        assert_eq!(
+            parse_console_command("fab").unwrap_err(),
+            ConsoleError::WrongArgs("fabricate", "a drone type")
+        );
+        let tasks = compile_program_to_tasks(&program("repair drone #3")).unwrap();
+        assert_eq!(tasks, vec![Task::Repair(3)]);
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        assert_eq!(
+            parse_console_command("dance (1,1)").unwrap_err(),
+            ConsoleError::UnknownCommand
+        );
+        assert!(matches!(
+            parse_console_command("drone x: move to (1,1)"),
+            Err(ConsoleError::InvalidDrone(_))
+        ));
+        assert!(matches!(
+            parse_console_command("move to (1)"),
+            Err(ConsoleError::InvalidCoord(_))
+        ));
+    }
+}
@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct TileCoord3 {
     pub x: i32,
     pub y: i32,
@@ -11,6 +11,15 @@ impl TileCoord3 {
     pub fn new(x: i32, y: i32, z: i32) -> Self {
         Self { x, y, z }
     }
+
+    pub fn manhattan(self, other: TileCoord3) -> i32 {
+        (self.x - other.x).abs() + (self.y - other.y).abs() + (self.z - other.z).abs()
+    }
+
+    // Within `reach` steps horizontally on the same level.
+    pub fn is_within(self, other: TileCoord3, reach: i32) -> bool {
+        self.z == other.z && (self.x - other.x).abs() + (self.y - other.y).abs() <= reach
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
@@ -71,6 +80,32 @@ impl TileBox3 {
     }
 }
 
+// Serde helper for maps keyed by tile: formats like JSON only take string
+// keys, so the map goes out as a list of pairs, sorted to keep saves stable.
+pub(crate) mod coord_map {
+    use std::collections::HashMap;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::TileCoord3;
+
+    pub fn serialize<V: Serialize, S: Serializer>(
+        map: &HashMap<TileCoord3, V>,
+        s: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut pairs: Vec<_> = map.iter().collect();
+        pairs.sort_by_key(|(c, _)| (c.z, c.y, c.x));
+        pairs.serialize(s)
+    }
+
+    pub fn deserialize<'de, V: Deserialize<'de>, D: Deserializer<'de>>(
+        d: D,
+    ) -> Result<HashMap<TileCoord3, V>, D::Error> {
+        let pairs: Vec<(TileCoord3, V)> = Vec::deserialize(d)?;
+        Ok(pairs.into_iter().collect())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -98,6 +133,15 @@ mod tests {
         assert_eq!(tiles.len(), 4);
     }
 
+    #[test]
+    fn distance_and_reach() {
+        let target = TileCoord3::new(3, 1, 0);
+        assert_eq!(TileCoord3::new(0, 0, 1).manhattan(target), 5);
+        assert!(TileCoord3::new(3, 0, 0).is_within(target, 1));
+        assert!(!TileCoord3::new(2, 0, 0).is_within(target, 1));
+        assert!(!TileCoord3::new(3, 0, 1).is_within(target, 1));
+    }
+
     #[test]
     fn border_includes_edges() {
         let b = TileBox3::new(TileCoord3::new(0, 0, 0), TileCoord3::new(2, 2, 0));
@@ -1,13 +1,144 @@
 use serde::{Deserialize, Serialize};
 
-use crate::tasks::Task;
+use crate::archetypes::Archetype;
+use crate::coords::TileCoord3;
+use crate::pathfinding::Path;
+use crate::resources::Resources;
+use crate::tasks::{Task, TaskId, TaskKind};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum DroneStatus {
     Idle,
     Thinking,
+    Moving,
     Working,
     Finished,
+    // Battery ran low: heading to a charger or recharging on one
+    LowPower,
+    // Broke down mid-work; stays put with its task until repaired
+    Broken,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Durability {
+    pub current: u32,
+    pub max: u32,
+}
+
+impl Durability {
+    pub fn new(max: u32) -> Self {
+        Self { current: max, max }
+    }
+
+    pub fn wear(&mut self, amount: u32) {
+        self.current = self.current.saturating_sub(amount);
+    }
+
+    pub fn restore(&mut self) {
+        self.current = self.max;
+    }
+
+    // Chance of breaking down on a tick of work: none when new, up to 2%
+    // when nearly worn out, and certain once worn out.
+    pub fn breakdown_chance(&self) -> f64 {
+        if self.current == 0 {
+            return 1.0;
+        }
+        let worn = 1.0 - f64::from(self.current) / f64::from(self.max.max(1));
+        worn.max(0.0) * 0.02
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Battery {
+    pub charge: u32,
+    pub capacity: u32,
+    // Energy spent per tile moved and per tile worked
+    pub move_cost: u32,
+    pub work_cost: u32,
+    // Energy regained per tick on a charger
+    pub recharge_rate: u32,
+}
+
+impl Battery {
+    // A full battery with the default drain and recharge rates.
+    pub fn new(capacity: u32) -> Self {
+        Self {
+            charge: capacity,
+            capacity,
+            move_cost: 1,
+            work_cost: 2,
+            recharge_rate: 10,
+        }
+    }
+
+    // Below a quarter the drone drops its work and goes to recharge.
+    pub fn is_low(&self) -> bool {
+        self.charge * 4 <= self.capacity
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.charge >= self.capacity
+    }
+
+    pub fn percent(&self) -> u32 {
+        (self.charge * 100).checked_div(self.capacity).unwrap_or(0)
+    }
+
+    pub fn drain(&mut self, amount: u32) {
+        self.charge = self.charge.saturating_sub(amount);
+    }
+
+    pub fn recharge(&mut self) {
+        self.charge = (self.charge + self.recharge_rate).min(self.capacity);
+    }
+}
+
+// Items a drone is carrying, up to `capacity` units of any kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cargo {
+    pub items: Resources,
+    pub capacity: u32,
+}
+
+impl Cargo {
+    pub fn new(capacity: u32) -> Self {
+        Self {
+            items: Resources::default(),
+            capacity,
+        }
+    }
+
+    pub fn used(&self) -> u32 {
+        self.items.total()
+    }
+
+    pub fn space(&self) -> u32 {
+        self.capacity.saturating_sub(self.used())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.space() == 0
+    }
+
+    // Takes as much of `items` as fits, stone first, and returns the rest.
+    pub fn load(&mut self, mut items: Resources) -> Resources {
+        let stone = items.stone.min(self.space());
+        self.items.add_stone(stone);
+        items.stone -= stone;
+        let iron = items.iron.min(self.space());
+        self.items.add_iron(iron);
+        items.iron -= iron;
+        items
+    }
+
+    pub fn unload(&mut self) -> Resources {
+        std::mem::take(&mut self.items)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -15,6 +146,22 @@ pub struct Drone {
     pub id: u32,
     pub status: DroneStatus,
     pub current_task: Option<Task>,
+    pub current_task_id: Option<TaskId>,
+    // Preferred kind of work; the scheduler offers matching tasks first
+    pub affinity: Option<TaskKind>,
+    pub position: TileCoord3,
+    // Tiles moved per tick
+    pub speed: u32,
+    // Index of the next patrol waypoint
+    pub waypoint: usize,
+    pub battery: Battery,
+    pub cargo: Cargo,
+    // Damage dealt to a tile per tick of mining
+    pub mining_power: u32,
+    // Worn down one point per tick of work
+    pub durability: Durability,
+    // None for a general-purpose drone that takes any task
+    pub archetype: Option<Archetype>,
 }
 
 impl Drone {
@@ -23,8 +170,57 @@ impl Drone {
             id,
             status: DroneStatus::Idle,
             current_task: None,
+            current_task_id: None,
+            affinity: None,
+            position: TileCoord3::new(0, 0, 0),
+            speed: 1,
+            waypoint: 0,
+            battery: Battery::new(200),
+            cargo: Cargo::new(10),
+            mining_power: 2,
+            durability: Durability::new(300),
+            archetype: None,
+        }
+    }
+
+    // A drone of the given type, starting with its stats.
+    pub fn of_type(id: u32, archetype: &Archetype) -> Self {
+        Self {
+            speed: archetype.speed,
+            battery: Battery::new(archetype.battery_capacity),
+            cargo: Cargo::new(archetype.cargo_capacity),
+            mining_power: archetype.mining_power,
+            archetype: Some(archetype.clone()),
+            ..Self::new(id)
         }
     }
+
+    pub fn accepts(&self, kind: TaskKind) -> bool {
+        self.archetype.as_ref().is_none_or(|a| a.accepts(kind))
+    }
+
+    pub fn type_name(&self) -> Option<&str> {
+        self.archetype.as_ref().map(|a| a.name.as_str())
+    }
+
+    pub fn at(mut self, position: TileCoord3) -> Self {
+        self.position = position;
+        self
+    }
+
+    // Moves up to `speed` tiles along `path`, which starts where the drone
+    // stands, as far as the battery allows.
+    pub fn follow(&mut self, path: &Path) {
+        self.status = DroneStatus::Moving;
+        let affordable = self
+            .battery
+            .charge
+            .checked_div(self.battery.move_cost)
+            .unwrap_or(u32::MAX);
+        let steps = (self.speed.min(affordable) as usize).min(path.len());
+        self.battery.drain(steps as u32 * self.battery.move_cost);
+        self.position = path.tiles[steps];
+    }
 }
 
 #[cfg(test)]
@@ -37,5 +233,83 @@ mod tests {
         assert_eq!(d.id, 1);
         assert_eq!(d.status, DroneStatus::Idle);
         assert!(d.current_task.is_none());
+        assert_eq!(d.position, TileCoord3::new(0, 0, 0));
+    }
+
+    #[test]
+    fn typed_drone_takes_its_stats() {
+        let d = Drone::of_type(4, &Archetype::hauler());
+        assert_eq!(d.speed, 2);
+        assert_eq!(d.cargo.capacity, 40);
+        assert_eq!(d.type_name(), Some("Hauler"));
+        assert!(d.accepts(TaskKind::Haul));
+        assert!(!d.accepts(TaskKind::Mine));
+        assert!(Drone::new(1).accepts(TaskKind::Mine));
+    }
+
+    #[test]
+    fn follow_respects_speed() {
+        let mut d = Drone::new(1);
+        d.speed = 2;
+        let path = Path {
+            tiles: (0..4).map(|x| TileCoord3::new(x, 0, 0)).collect(),
+        };
+        d.follow(&path);
+        assert_eq!(d.position, TileCoord3::new(2, 0, 0));
+        assert_eq!(d.status, DroneStatus::Moving);
+        // Never overshoots the end of the path
+        d.follow(&path.from_tile(d.position).unwrap());
+        assert_eq!(d.position, TileCoord3::new(3, 0, 0));
+        assert_eq!(d.battery.charge, 197);
+    }
+
+    #[test]
+    fn battery_drains_and_recharges() {
+        let mut d = Drone::new(1);
+        d.battery = Battery::new(20);
+        d.battery.charge = 6;
+        assert!(!d.battery.is_low());
+        d.speed = 10;
+        let path = Path {
+            tiles: (0..10).map(|x| TileCoord3::new(x, 0, 0)).collect(),
+        };
+        // Only enough charge for six moves
+        d.follow(&path);
+        assert_eq!(d.position, TileCoord3::new(6, 0, 0));
+        assert_eq!(d.battery.charge, 0);
+        assert!(d.battery.is_low());
+        d.battery.recharge();
+        d.battery.recharge();
+        d.battery.recharge();
+        assert!(d.battery.is_full());
+        assert_eq!(d.battery.percent(), 100);
+    }
+
+    #[test]
+    fn wear_raises_breakdown_chance() {
+        let mut d = Durability::new(100);
+        assert_eq!(d.breakdown_chance(), 0.0);
+        d.wear(50);
+        assert!((d.breakdown_chance() - 0.01).abs() < 1e-9);
+        d.wear(80);
+        assert_eq!(d.current, 0);
+        assert_eq!(d.breakdown_chance(), 1.0);
+        d.restore();
+        assert_eq!(d.current, 100);
+    }
+
+    #[test]
+    fn cargo_fills_to_capacity_and_serializes() {
+        let mut d = Drone::new(1);
+        d.cargo = Cargo::new(5);
+        let left = d.cargo.load(Resources { stone: 3, iron: 4 });
+        assert_eq!(left, Resources { stone: 0, iron: 2 });
+        assert!(d.cargo.is_full());
+
+        let json = serde_json::to_string(&d).unwrap();
+        let back: Drone = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.cargo, d.cargo);
+        assert_eq!(d.cargo.unload(), Resources { stone: 3, iron: 2 });
+        assert!(d.cargo.is_empty());
     }
 }
@@ -16,12 +16,19 @@ pub enum CompileError {
     InvalidArg,
     #[error("Schema error: {0}")]
     SchemaError(String),
+    #[error("Unknown drone type: {0}")]
+    UnknownDroneType(String),
+    #[error("A patrol needs at least two waypoints")]
+    ShortPatrol,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Program {
     pub version: u32,
     pub node: String,
+    // Binds every task in the program to this `Drone::id`
+    #[serde(default)]
+    pub drone: Option<u32>,
     pub statements: Vec<Statement>,
 }
 
@@ -77,6 +84,9 @@ pub enum Expr {
     IntLiteral {
         value: i64,
     },
+    StringLiteral {
+        value: String,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -88,9 +98,24 @@ pub struct Coord {
     pub z: i32,
 }
 
-#[derive(Default)]
-struct Scope {
-    vars: std::collections::HashMap<String, Expr>,
+// Types a `Let` may bind. `Value` holds whatever `recv()` hands back.
+const LET_TYPES: [&str; 5] = ["TileBox", "TileCoord", "Int", "Text", "Value"];
+
+// Calls that talk to other drones; they need a program running across
+// ticks rather than a one-off compile.
+pub(crate) const COORDINATION_FUNCS: [&str; 5] = ["send", "recv", "set", "set_flag", "wait_for"];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub(crate) struct Scope {
+    pub(crate) vars: std::collections::BTreeMap<String, Expr>,
+}
+
+pub(crate) fn check_let_type(ty: &str) -> Result<(), CompileError> {
+    if LET_TYPES.contains(&ty) {
+        Ok(())
+    } else {
+        Err(CompileError::UnsupportedNode(format!("Let type {}", ty)))
+    }
 }
 
 fn expr_to_box3(e: &Expr, scope: &Scope) -> Result<TileBox3, CompileError> {
@@ -125,6 +150,81 @@ fn expr_to_box3(e: &Expr, scope: &Scope) -> Result<TileBox3, CompileError> {
     }
 }
 
+fn expr_to_coord(e: &Expr, scope: &Scope) -> Result<TileCoord3, CompileError> {
+    match e {
+        Expr::TileCoord { x, y, z } => Ok(TileCoord3::new(*x, *y, *z)),
+        Expr::VarRef { name } => {
+            let bound = scope
+                .vars
+                .get(name)
+                .ok_or_else(|| CompileError::UnknownVar(name.clone()))?;
+            expr_to_coord(bound, scope)
+        }
+        _ => Err(CompileError::InvalidArg),
+    }
+}
+
+pub(crate) fn compile_call(func: &str, args: &[Expr], scope: &Scope) -> Result<Task, CompileError> {
+    if COORDINATION_FUNCS.contains(&func) {
+        return Err(CompileError::UnsupportedNode(format!(
+            "{} only works in a running program",
+            func
+        )));
+    }
+    let box_task: Option<fn(TileBox3) -> Task> = match func {
+        "mine_box" => Some(Task::MineBox),
+        "build_wall" => Some(Task::BuildWall),
+        "build_floor" => Some(Task::BuildFloor),
+        "build_wall_on_border" => Some(Task::BuildOnBorder),
+        "build_charger" => Some(Task::BuildCharger),
+        "haul" => Some(Task::Haul),
+        "guard" => Some(Task::Guard),
+        _ => None,
+    };
+    if let Some(make_task) = box_task {
+        if args.len() != 1 {
+            return Err(CompileError::InvalidArg);
+        }
+        return Ok(make_task(expr_to_box3(&args[0], scope)?));
+    }
+    match func {
+        // Queues a new drone at the core, e.g. fabricate("miner")
+        "fabricate" => match args {
+            [Expr::StringLiteral { value }] => Ok(Task::Fabricate(value.clone())),
+            _ => Err(CompileError::InvalidArg),
+        },
+        // Sends a drone to fix a broken one, e.g. repair(3)
+        "repair" => match args {
+            [Expr::IntLiteral { value }] => u32::try_from(*value)
+                .map(Task::Repair)
+                .map_err(|_| CompileError::InvalidArg),
+            _ => Err(CompileError::InvalidArg),
+        },
+        // Both walk an A* path to the tile; `path_to` reads better in
+        // programs that chain several moves
+        "move_to" | "path_to" => {
+            if args.len() != 1 {
+                return Err(CompileError::InvalidArg);
+            }
+            Ok(Task::MoveTo(expr_to_coord(&args[0], scope)?))
+        }
+        "patrol" => {
+            if args.len() < 2 {
+                return Err(CompileError::ShortPatrol);
+            }
+            let waypoints = args
+                .iter()
+                .map(|a| expr_to_coord(a, scope))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(Task::Patrol(waypoints))
+        }
+        _ => Err(CompileError::UnsupportedNode(format!(
+            "Unknown function {}",
+            func
+        ))),
+    }
+}
+
 pub fn compile_program_to_tasks(p: &Program) -> Result<Vec<Task>, CompileError> {
     if p.node != "Program" {
         return Err(CompileError::InvalidRoot);
@@ -134,22 +234,14 @@ pub fn compile_program_to_tasks(p: &Program) -> Result<Vec<Task>, CompileError>
     for stmt in &p.statements {
         match stmt {
             Statement::Let { name, ty, value } => {
-                if ty != "TileBox" {
-                    return Err(CompileError::UnsupportedNode(format!("Let type {}", ty)));
-                }
+                check_let_type(ty)?;
                 scope.vars.insert(name.clone(), value.clone());
             }
             Statement::ExprStmt { expr } => match expr {
-                Expr::Call { func, args } if func == "mine_box" => {
-                    if args.len() != 1 {
-                        return Err(CompileError::InvalidArg);
-                    }
-                    let b = expr_to_box3(&args[0], &scope)?;
-                    tasks.push(Task::MineBox(b));
-                }
+                Expr::Call { func, args } => tasks.push(compile_call(func, args, &scope)?),
                 _ => {
                     return Err(CompileError::UnsupportedNode(
-                        "Only mine_box supported in M1".into(),
+                        "Only calls supported as statements".into(),
                     ));
                 }
             },
@@ -202,6 +294,109 @@ mod tests {
                 assert_eq!(b.width(), 2);
                 assert_eq!(b.height(), 2);
             }
+            other => panic!("unexpected task {:?}", other),
         }
     }
+
+    #[test]
+    fn compile_build_wall_on_border() {
+        let program_json = json!({
+            "version": 1,
+            "node": "Program",
+            "statements": [
+                {
+                    "node": "ExprStmt",
+                    "expr": {
+                        "node": "Call",
+                        "func": "build_wall_on_border",
+                        "args": [{
+                            "node": "TileBoxFromCoords",
+                            "min": { "node": "TileCoord", "x": 0, "y": 0 },
+                            "max": { "node": "TileCoord", "x": 4, "y": 4 }
+                        }]
+                    }
+                }
+            ]
+        });
+        let prog: Program = serde_json::from_value(program_json).unwrap();
+        let tasks = compile_program_to_tasks(&prog).unwrap();
+        assert!(matches!(tasks[0], Task::BuildOnBorder(b) if b.width() == 5));
+    }
+
+    #[test]
+    fn compile_patrol_with_coord_binding() {
+        let program_json = json!({
+            "version": 1,
+            "node": "Program",
+            "statements": [
+                {
+                    "node": "Let",
+                    "name": "start",
+                    "ty": "TileCoord",
+                    "value": { "node": "TileCoord", "x": 20, "y": 10 }
+                },
+                {
+                    "node": "ExprStmt",
+                    "expr": {
+                        "node": "Call",
+                        "func": "patrol",
+                        "args": [
+                            { "node": "VarRef", "name": "start" },
+                            { "node": "TileCoord", "x": 25, "y": 10, "z": 0 }
+                        ]
+                    }
+                }
+            ]
+        });
+        let prog: Program = serde_json::from_value(program_json).unwrap();
+        let tasks = compile_program_to_tasks(&prog).unwrap();
+        assert_eq!(
+            tasks,
+            vec![Task::Patrol(vec![
+                TileCoord3::new(20, 10, 0),
+                TileCoord3::new(25, 10, 0)
+            ])]
+        );
+    }
+
+    #[test]
+    fn compile_path_to() {
+        let prog: Program = serde_json::from_value(json!({
+            "version": 1,
+            "node": "Program",
+            "statements": [{
+                "node": "ExprStmt",
+                "expr": {
+                    "node": "Call",
+                    "func": "path_to",
+                    "args": [{ "node": "TileCoord", "x": 3, "y": 4, "z": 1 }]
+                }
+            }]
+        }))
+        .unwrap();
+        let tasks = compile_program_to_tasks(&prog).unwrap();
+        assert_eq!(tasks, vec![Task::MoveTo(TileCoord3::new(3, 4, 1))]);
+    }
+
+    #[test]
+    fn compile_fabricate() {
+        let call = |args| {
+            let prog: Program = serde_json::from_value(json!({
+                "version": 1,
+                "node": "Program",
+                "statements": [{
+                    "node": "ExprStmt",
+                    "expr": { "node": "Call", "func": "fabricate", "args": args }
+                }]
+            }))
+            .unwrap();
+            compile_program_to_tasks(&prog)
+        };
+        let tasks = call(json!([{ "node": "StringLiteral", "value": "miner" }])).unwrap();
+        assert_eq!(tasks, vec![Task::Fabricate("miner".to_string())]);
+        assert!(matches!(
+            call(json!([{ "node": "IntLiteral", "value": 3 }])),
+            Err(CompileError::InvalidArg)
+        ));
+    }
 }
@@ -1,82 +1,2029 @@
+use std::collections::HashSet;
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use rayon::prelude::*;
+use thiserror::Error;
+
+use crate::archetypes::{Archetype, builtin_archetypes, find_archetype};
+use crate::blackboard::Blackboard;
+use crate::chunk_graph::ChunkGraph;
+use crate::clock::SimClock;
+use crate::command::{Command, CommandError, CommandOutput, CommandRecord};
+use crate::coords::TileCoord3;
 use crate::drones::{Drone, DroneStatus};
-use crate::tasks::{TaskManager, apply_task};
+use crate::dsl_ast::{CompileError, Program, compile_program_to_tasks};
+use crate::events::{EngineEvent, EventLog};
+use crate::hash::{StateHashes, hash_of};
+use crate::history::{History, HistoryError, Snapshot};
+use crate::pathfinding::{Path, PathCache};
+use crate::planning::{Planned, RoutePlans, RouteQuery};
+use crate::replay::{Checkpoint, Replay};
+use crate::reservations::{ReservationConflict, Reservations};
+use crate::save::{SAVE_VERSION, SaveError, SaveFormat, SaveGame, encode};
+use crate::scenario::Wave;
+use crate::script::{Script, ScriptId};
+use crate::session::{Session, SessionState};
+use crate::tasks::{REPAIR_COST, Task, TaskId, TaskKind, TaskManager, TaskState, apply_task_at};
+use crate::tile::TileKind;
 use crate::world::World;
 
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum AssignError {
+    #[error("No task #{0}")]
+    UnknownTask(TaskId),
+    #[error("No drone #{0}")]
+    UnknownDrone(u32),
+    #[error("Task #{0} has already started")]
+    NotPending(TaskId),
+    #[error("Drone #{0} doesn't take {} tasks", .1.label())]
+    Incapable(u32, TaskKind),
+    #[error("Unknown drone type: {0}")]
+    UnknownDroneType(String),
+}
+
+// Fewest drones handed to a thread when guessing routes, so small games
+// stay on one.
+const DRONES_PER_THREAD: usize = 16;
+
+// Core damage from each enemy in a wave that gets through, and how many
+// enemies each guarding drone holds off. Stands in for enemies walking the
+// map until they exist.
+pub const ENEMY_DAMAGE: u32 = 5;
+pub const ENEMIES_PER_GUARD: u32 = 2;
+
+enum Travel {
+    Arrived,
+    Moving,
+    NoPath,
+}
+
 #[derive(Debug)]
 pub struct Engine {
     pub world: World,
     pub drones: Vec<Drone>,
     pub tasks: TaskManager,
+    pub reservations: Reservations,
+    pub paths: PathCache,
+    pub chunks: ChunkGraph,
+    // Paths found for this tick's drones before they act
+    plans: RoutePlans,
+    // Drone types the core can fabricate
+    pub archetypes: Vec<Archetype>,
+    // Fabrication order being built and the ticks it still needs
+    fabricating: Option<(TaskId, u32)>,
+    // Drives breakdowns; seeded so runs can be reproduced
+    rng: ChaCha8Rng,
+    // Values and messages shared by running programs
+    pub blackboard: Blackboard,
+    pub scripts: Vec<Script>,
+    // Tick counter and game speed
+    pub clock: SimClock,
+    // Attacks the scenario has scheduled, by tick
+    pub waves: Vec<Wave>,
+    // Setup, running or over, and how the game is won
+    pub session: Session,
+    events: EventLog,
+    // Commands and state hashes since `start_recording`
+    recording: Option<Replay>,
+    // Snapshots for rolling back and undo; off unless enabled
+    history: Option<History>,
+    determinism_check: bool,
 }
 
 impl Engine {
-    pub fn new(world: World, drones: Vec<Drone>) -> Self {
+    pub fn new(mut world: World, drones: Vec<Drone>) -> Self {
+        let chunks = ChunkGraph::new(&world);
+        let tasks = TaskManager::new();
+        let events = EventLog::new(&mut world, &drones, &tasks, SessionState::Setup);
         Self {
             world,
             drones,
-            tasks: TaskManager::new(),
+            tasks,
+            reservations: Reservations::new(),
+            paths: PathCache::new(),
+            chunks,
+            plans: RoutePlans::default(),
+            archetypes: builtin_archetypes(),
+            fabricating: None,
+            rng: ChaCha8Rng::seed_from_u64(0),
+            blackboard: Blackboard::new(),
+            scripts: Vec::new(),
+            clock: SimClock::default(),
+            waves: Vec::new(),
+            session: Session::default(),
+            events,
+            recording: None,
+            history: None,
+            determinism_check: false,
         }
     }
 
-    // Processes a single step:
-    // - Pick an idle drone
-    // - If a task is pending, move to Thinking -> Working
-    // - Apply the task immediately for Milestone 1
-    // - Mark drone Finished and then back to Idle
-    pub fn tick(&mut self) {
-        // Find an available drone
-        let drone_idx = self
+    // Copies out the whole game state, e.g. for writing to disk.
+    pub fn to_save(&self) -> SaveGame {
+        let mut world = self.world.clone();
+        world.set_journaling(false);
+        self.save_with(world)
+    }
+
+    fn save_with(&self, world: World) -> SaveGame {
+        SaveGame {
+            version: SAVE_VERSION,
+            world,
+            drones: self.drones.clone(),
+            tasks: self.tasks.clone(),
+            reservations: self.reservations.clone(),
+            archetypes: self.archetypes.clone(),
+            fabricating: self.fabricating,
+            rng: self.rng.clone(),
+            blackboard: self.blackboard.clone(),
+            scripts: self.scripts.clone(),
+            clock: self.clock.clone(),
+            waves: self.waves.clone(),
+            session: self.session.clone(),
+        }
+    }
+
+    pub fn from_save(save: SaveGame) -> Self {
+        let mut engine = Self::new(save.world, save.drones);
+        engine.tasks = save.tasks;
+        engine.reservations = save.reservations;
+        engine.archetypes = save.archetypes;
+        engine.fabricating = save.fabricating;
+        engine.rng = save.rng;
+        engine.blackboard = save.blackboard;
+        engine.scripts = save.scripts;
+        engine.clock = save.clock;
+        engine.waves = save.waves;
+        engine.session = save.session;
+        engine.events = EventLog::new(
+            &mut engine.world,
+            &engine.drones,
+            &engine.tasks,
+            engine.session.state(),
+        );
+        engine
+    }
+
+    // Swaps in a state restored from history. Caches are rebuilt, as the
+    // map may have gone back to an earlier revision.
+    fn restore_state(&mut self, save: SaveGame) {
+        self.world = save.world;
+        self.drones = save.drones;
+        self.tasks = save.tasks;
+        self.reservations = save.reservations;
+        self.archetypes = save.archetypes;
+        self.fabricating = save.fabricating;
+        self.rng = save.rng;
+        self.blackboard = save.blackboard;
+        self.scripts = save.scripts;
+        self.clock = save.clock;
+        self.waves = save.waves;
+        self.session = save.session;
+        self.paths = PathCache::new();
+        self.chunks = ChunkGraph::new(&self.world);
+    }
+
+    pub fn save(
+        &self,
+        path: impl AsRef<std::path::Path>,
+        format: SaveFormat,
+    ) -> Result<(), SaveError> {
+        self.to_save().write(path, format)
+    }
+
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self, SaveError> {
+        Ok(Self::from_save(SaveGame::read(path)?))
+    }
+
+    pub fn session_state(&self) -> SessionState {
+        match self.session.state() {
+            SessionState::Running if self.clock.is_paused() => SessionState::Paused,
+            state => state,
+        }
+    }
+
+    // Ends setup so the clock starts driving ticks. False if the game had
+    // already started.
+    pub fn start(&mut self) -> bool {
+        self.session.start()
+    }
+
+    // Waves that have struck without destroying the core. A lost game
+    // stopped on the tick the core fell, so none of that tick's waves count.
+    pub fn waves_survived(&self) -> u32 {
+        let tick = self.clock.tick();
+        let survived = |w: &&Wave| match self.session.state() {
+            SessionState::Lost => w.tick < tick,
+            _ => w.tick <= tick,
+        };
+        self.waves.iter().filter(survived).count() as u32
+    }
+
+    // The next wave still to come, numbered from 1, and its tick.
+    pub fn next_wave(&self) -> Option<(usize, &Wave)> {
+        self.waves
+            .iter()
+            .enumerate()
+            .find(|(_, w)| w.tick > self.clock.tick())
+            .map(|(i, w)| (i + 1, w))
+    }
+
+    // Fingerprint of the simulation state, for checking replays and
+    // lockstep peers. Game speed and unspent frame time are left out; they
+    // don't change what happens.
+    pub fn state_hash(&self) -> u64 {
+        self.state_hashes().combined()
+    }
+
+    pub fn state_hashes(&self) -> StateHashes {
+        StateHashes {
+            tick: self.clock.tick(),
+            world: self.world.state_hash(),
+            drones: hash_of(&self.drones),
+            tasks: hash_of(&(&self.tasks, &self.reservations, &self.fabricating)),
+            rng: hash_of(&self.rng),
+            programs: hash_of(&(&self.scripts, &self.blackboard)),
+            setup: hash_of(&(&self.archetypes, &self.waves, &self.session)),
+        }
+    }
+
+    // Debug mode: every tick also runs on a copy restored from a save, and
+    // panics if the two end up in different states. Slow; for hunting down
+    // nondeterminism, not for play.
+    pub fn set_determinism_check(&mut self, on: bool) {
+        self.determinism_check = on;
+    }
+
+    // Applies a player command; if it succeeds it is recorded while a
+    // recording is running, and anything undone so far can't be redone.
+    pub fn apply(&mut self, command: Command) -> Result<CommandOutput, CommandError> {
+        let output = self.execute(command)?;
+        if let Some(history) = &mut self.history {
+            history.undone.clear();
+        }
+        Ok(output)
+    }
+
+    fn execute(&mut self, command: Command) -> Result<CommandOutput, CommandError> {
+        let record = CommandRecord {
+            tick: self.clock.tick(),
+            command: command.clone(),
+        };
+        let output = match command {
+            Command::QueueTask { task, drone } => {
+                self.check_task(&task)?;
+                let queued = match drone {
+                    Some(drone_id) => self.queue_task_for(task, drone_id),
+                    None => self.queue_task(task),
+                };
+                CommandOutput::Queued(vec![queued])
+            }
+            Command::QueueProgram(program) => CommandOutput::Queued(self.queue_program(&program)?),
+            Command::RunProgram(program) => CommandOutput::Started(self.run_program(program)?),
+            Command::CancelTask(id) => {
+                if !self.cancel_task(id) {
+                    return Err(CommandError::NotCancellable(id));
+                }
+                CommandOutput::Done
+            }
+            Command::AssignTask { task, drone } => {
+                self.assign_task(task, drone)?;
+                CommandOutput::Done
+            }
+            Command::SetRole { drone, role } => {
+                self.set_drone_affinity(drone, role)?;
+                CommandOutput::Done
+            }
+            Command::Fabricate(name) => {
+                let id = self.fabricate(&name)?;
+                CommandOutput::Queued(vec![(id, Vec::new())])
+            }
+            Command::AddStockpile(zone) => {
+                self.world.add_stockpile(zone);
+                CommandOutput::Done
+            }
+        };
+        // Failed commands changed nothing, so there is nothing to replay or
+        // undo
+        if let Some(recording) = &mut self.recording {
+            recording.commands.push(record.clone());
+        }
+        if let Some(history) = &mut self.history {
+            history.commands.push(record);
+        }
+        Ok(output)
+    }
+
+    // Starts logging commands from the current state, with a state hash
+    // every `hash_interval` ticks.
+    pub fn start_recording(&mut self, hash_interval: u64) {
+        self.recording = Some(Replay::new(
+            self.to_save(),
+            self.state_hash(),
+            hash_interval,
+        ));
+    }
+
+    // The recording so far, ending at the current state.
+    pub fn replay(&self) -> Option<Replay> {
+        let mut replay = self.recording.clone()?;
+        replay.end = Checkpoint {
+            tick: self.clock.tick(),
+            hash: self.state_hash(),
+        };
+        Some(replay)
+    }
+
+    pub fn stop_recording(&mut self) -> Option<Replay> {
+        let replay = self.replay();
+        self.recording = None;
+        replay
+    }
+
+    // Keeps a snapshot every `interval` ticks so the game can be rolled back
+    // and commands undone, within roughly `budget` bytes.
+    pub fn enable_history(&mut self, interval: u64, budget: usize) {
+        self.world.set_journaling(true);
+        self.history = Some(History::new(interval, budget));
+        self.take_snapshot();
+    }
+
+    pub fn disable_history(&mut self) {
+        self.world.set_journaling(false);
+        self.history = None;
+    }
+
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    fn take_snapshot(&mut self) {
+        if self.history.is_none() {
+            return;
+        }
+        let changed = self.world.take_journal();
+        let world = self.world.without_tiles();
+        let state =
+            encode(&self.save_with(world), SaveFormat::Binary).expect("game state always encodes");
+        let snapshot = Snapshot::new(self.clock.tick(), state);
+        if let Some(history) = &mut self.history {
+            history.push(snapshot, changed);
+        }
+    }
+
+    // Puts the game back to the latest snapshot at or before `tick` and
+    // returns the commands given since, which the caller may replay.
+    fn rewind(&mut self, tick: u64) -> Result<Vec<CommandRecord>, HistoryError> {
+        let history = self.history.as_mut().ok_or(HistoryError::Disabled)?;
+        if tick > self.clock.tick() {
+            return Err(HistoryError::InFuture(tick));
+        }
+        let (save, commands) = history.roll_back(tick, &mut self.world)?;
+        let from = save.clock.tick();
+        // A recording that started later can't be kept consistent
+        if self
+            .recording
+            .as_ref()
+            .is_some_and(|r| r.start.clock.tick() > from)
+        {
+            self.recording = None;
+        }
+        if let Some(recording) = &mut self.recording {
+            recording.commands.retain(|c| c.tick < from);
+            recording.checkpoints.retain(|c| c.tick <= from);
+        }
+        let speed = self.clock.speed();
+        self.restore_state(save);
+        self.clock.set_speed(speed);
+        self.world.set_journaling(true);
+        Ok(commands)
+    }
+
+    // Runs forward to `until`, giving each command at the tick it was
+    // first given. Ones past `until`, or past the end of a game that is now
+    // over sooner, are dropped.
+    fn replay_commands(&mut self, until: u64, commands: Vec<CommandRecord>) {
+        let mut commands = commands.into_iter().peekable();
+        loop {
+            let tick = self.clock.tick();
+            while let Some(record) = commands.next_if(|r| r.tick <= tick) {
+                // Only commands that worked are kept, so this can't fail
+                let _ = self.execute(record.command);
+            }
+            // Ticks stop once the game is over
+            if tick >= until || self.session.state().is_over() {
+                break;
+            }
+            self.tick();
+        }
+    }
+
+    // Rolls the game back to how it was at `tick`, before that tick's
+    // commands, and forgets everything since.
+    pub fn restore_to_tick(&mut self, tick: u64) -> Result<(), HistoryError> {
+        let commands = self.rewind(tick)?;
+        let later: Vec<_> = commands.into_iter().filter(|c| c.tick < tick).collect();
+        self.replay_commands(tick, later);
+        if let Some(history) = &mut self.history {
+            history.undone.clear();
+        }
+        Ok(())
+    }
+
+    // Takes back the last command: the game is rolled back to when it was
+    // given and run forward to the present tick without it.
+    pub fn undo(&mut self) -> Result<Command, HistoryError> {
+        let history = self.history.as_ref().ok_or(HistoryError::Disabled)?;
+        let last = history.commands.last().ok_or(HistoryError::NothingToUndo)?;
+        let now = self.clock.tick();
+        let mut commands = self.rewind(last.tick)?;
+        let undone = commands.pop().ok_or(HistoryError::NothingToUndo)?;
+        self.replay_commands(now, commands);
+        let command = undone.command.clone();
+        if let Some(history) = &mut self.history {
+            history.undone.push(undone);
+        }
+        Ok(command)
+    }
+
+    // Gives the last undone command again, at the tick it was first given.
+    pub fn redo(&mut self) -> Result<Command, HistoryError> {
+        let history = self.history.as_mut().ok_or(HistoryError::Disabled)?;
+        let record = history.undone.pop().ok_or(HistoryError::NothingToRedo)?;
+        let now = self.clock.tick();
+        let mut commands = match self.rewind(record.tick) {
+            Ok(commands) => commands,
+            Err(e) => {
+                if let Some(history) = &mut self.history {
+                    history.undone.push(record);
+                }
+                return Err(e);
+            }
+        };
+        let command = record.command.clone();
+        commands.push(record);
+        self.replay_commands(now, commands);
+        Ok(command)
+    }
+
+    pub fn reseed(&mut self, seed: u64) {
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
+    }
+
+    // Queues a task and reports every tile it shares with tasks that are
+    // already queued or running. The task is queued regardless; it simply
+    // waits until it can claim its whole footprint.
+    pub fn queue_task(&mut self, task: Task) -> (TaskId, Vec<ReservationConflict>) {
+        let footprint = task.footprint();
+        let id = self.tasks.tasks.len();
+        let mut conflicts = self.reservations.conflicts(id, &footprint);
+        let wanted: HashSet<_> = footprint.iter().copied().collect();
+        for (other_id, (other, state)) in self.tasks.tasks.iter().enumerate() {
+            if *state != TaskState::Pending {
+                continue;
+            }
+            for tile in other.footprint() {
+                if wanted.contains(&tile) {
+                    conflicts.push(ReservationConflict {
+                        tile,
+                        held_by: other_id,
+                    });
+                }
+            }
+        }
+        self.tasks.push(task);
+        (id, conflicts)
+    }
+
+    // Queues a task that only the drone with `drone_id` may pick up.
+    pub fn queue_task_for(
+        &mut self,
+        task: Task,
+        drone_id: u32,
+    ) -> (TaskId, Vec<ReservationConflict>) {
+        let (id, conflicts) = self.queue_task(task);
+        self.tasks.assign(id, drone_id);
+        (id, conflicts)
+    }
+
+    // Compiles a program and queues its tasks, bound to `program.drone`
+    // when the program names one.
+    pub fn queue_program(
+        &mut self,
+        program: &Program,
+    ) -> Result<Vec<(TaskId, Vec<ReservationConflict>)>, CompileError> {
+        let tasks = compile_program_to_tasks(program)?;
+        for t in &tasks {
+            self.check_task(t)?;
+        }
+        Ok(tasks
+            .into_iter()
+            .map(|t| match program.drone {
+                Some(drone_id) => self.queue_task_for(t, drone_id),
+                None => self.queue_task(t),
+            })
+            .collect())
+    }
+
+    fn check_task(&self, task: &Task) -> Result<(), CompileError> {
+        match task {
+            Task::Fabricate(name) if find_archetype(&self.archetypes, name).is_none() => {
+                Err(CompileError::UnknownDroneType(name.clone()))
+            }
+            Task::Patrol(waypoints) if waypoints.len() < 2 => Err(CompileError::ShortPatrol),
+            _ => Ok(()),
+        }
+    }
+
+    // Starts a program that runs across ticks, so it can wait on flags and
+    // messages from other drones. Each call queues one task and waits for it
+    // to finish before moving on.
+    pub fn run_program(&mut self, program: Program) -> Result<ScriptId, CompileError> {
+        if program.node != "Program" {
+            return Err(CompileError::InvalidRoot);
+        }
+        self.scripts.push(Script::new(program));
+        Ok(self.scripts.len() - 1)
+    }
+
+    fn step_scripts(&mut self) {
+        for i in 0..self.scripts.len() {
+            while let Some(task) = self.scripts[i].step(&self.tasks, &mut self.blackboard) {
+                if let Err(e) = self.check_task(&task) {
+                    self.scripts[i].fail(e.to_string());
+                    break;
+                }
+                let id = match self.scripts[i].drone() {
+                    Some(drone_id) => self.queue_task_for(task, drone_id).0,
+                    None => self.queue_task(task).0,
+                };
+                self.scripts[i].wait_on(id);
+            }
+        }
+    }
+
+    // Queues a new drone of the named type at the core.
+    pub fn fabricate(&mut self, archetype: &str) -> Result<TaskId, AssignError> {
+        let name = find_archetype(&self.archetypes, archetype)
+            .ok_or_else(|| AssignError::UnknownDroneType(archetype.to_string()))?
+            .name
+            .clone();
+        Ok(self.queue_task(Task::Fabricate(name)).0)
+    }
+
+    // The order the core is building and how many ticks it has left.
+    pub fn fabrication_progress(&self) -> Option<(TaskId, u32)> {
+        self.fabricating
+    }
+
+    // Binds a queued task to one drone, e.g. "assign task #4 to drone #2".
+    pub fn assign_task(&mut self, id: TaskId, drone_id: u32) -> Result<(), AssignError> {
+        match self.tasks.state(id) {
+            None => return Err(AssignError::UnknownTask(id)),
+            Some(TaskState::Pending) => {}
+            Some(_) => return Err(AssignError::NotPending(id)),
+        }
+        let Some(drone) = self.drones.iter().find(|d| d.id == drone_id) else {
+            return Err(AssignError::UnknownDrone(drone_id));
+        };
+        let kind = self.tasks.get(id).map(|t| t.kind());
+        if let Some(kind) = kind
+            && !drone.accepts(kind)
+        {
+            return Err(AssignError::Incapable(drone_id, kind));
+        }
+        self.tasks.assign(id, drone_id);
+        Ok(())
+    }
+
+    pub fn set_drone_affinity(
+        &mut self,
+        drone_id: u32,
+        affinity: Option<TaskKind>,
+    ) -> Result<(), AssignError> {
+        let drone = self
+            .drones
+            .iter_mut()
+            .find(|d| d.id == drone_id)
+            .ok_or(AssignError::UnknownDrone(drone_id))?;
+        drone.affinity = affinity;
+        Ok(())
+    }
+
+    pub fn cancel_task(&mut self, id: TaskId) -> bool {
+        let cancelled = self.tasks.cancel(id);
+        if cancelled {
+            self.reservations.release(id);
+            // A drone half-built at the core is scrapped for a full refund
+            if let Some((building, _)) = self.fabricating
+                && building == id
+            {
+                self.fabricating = None;
+                if let Some(Task::Fabricate(name)) = self.tasks.get(id)
+                    && let Some(a) = find_archetype(&self.archetypes, name)
+                {
+                    self.world.resources.add(&a.cost);
+                }
+            }
+            // Free any drone still busy with it, e.g. on patrol
+            for d in &mut self.drones {
+                if d.current_task_id == Some(id) {
+                    if d.status != DroneStatus::Broken {
+                        d.status = DroneStatus::Idle;
+                    }
+                    d.waypoint = 0;
+                    d.current_task = None;
+                    d.current_task_id = None;
+                }
+            }
+        }
+        cancelled
+    }
+
+    // Picks which of the `free` drones (indices into `drones`) should take
+    // `task`: its assignee if it has one, otherwise a capable drone whose
+    // affinity matches, then one without an affinity, then any capable one.
+    fn pick_drone(&self, id: TaskId, task: &Task, free: &[usize]) -> Result<usize, String> {
+        let kind = task.kind();
+        if let Some(assignee) = self.tasks.assigned_drone(id) {
+            if let Some(slot) = free
+                .iter()
+                .position(|&i| self.drones[i].id == assignee && self.drones[i].accepts(kind))
+            {
+                return Ok(slot);
+            }
+            return match self.drones.iter().find(|d| d.id == assignee) {
+                Some(d) if !d.accepts(kind) => {
+                    Err(AssignError::Incapable(assignee, kind).to_string())
+                }
+                Some(_) => Err(format!("Waiting for drone #{}", assignee)),
+                None => Err(format!("Drone #{} does not exist", assignee)),
+            };
+        }
+        if !self.drones.iter().any(|d| d.accepts(kind)) {
+            return Err(format!("No drone can do {}", kind.label().to_lowercase()));
+        }
+        let capable = |i: usize| self.drones[i].accepts(kind);
+        let by_affinity = |want: Option<TaskKind>| {
+            free.iter()
+                .position(|&i| capable(i) && self.drones[i].affinity == want)
+        };
+        by_affinity(Some(kind))
+            .or_else(|| by_affinity(None))
+            .or_else(|| free.iter().position(|&i| capable(i)))
+            .ok_or_else(|| "Waiting for a free drone".to_string())
+    }
+
+    fn start_on_drone(&mut self, idx: usize, id: TaskId, task: Task) {
+        let drone = &mut self.drones[idx];
+        drone.status = DroneStatus::Working;
+        drone.current_task = Some(task);
+        drone.current_task_id = Some(id);
+        drone.waypoint = 0;
+    }
+
+    fn release_drone(&mut self, idx: usize, id: TaskId) {
+        self.reservations.release(id);
+        let drone = &mut self.drones[idx];
+        drone.status = DroneStatus::Idle;
+        drone.current_task = None;
+        drone.current_task_id = None;
+    }
+
+    // Moves drone `idx` one tick along a path to within `reach` of `goal`.
+    fn travel(&mut self, idx: usize, goal: TileCoord3, reach: i32) -> Travel {
+        let position = self.drones[idx].position;
+        if position.is_within(goal, reach) {
+            return Travel::Arrived;
+        }
+        match self.find_route(&RouteQuery::travel(position, goal, reach)) {
+            Some(path) => {
+                self.drones[idx].follow(&path);
+                Travel::Moving
+            }
+            None => Travel::NoPath,
+        }
+    }
+
+    // Path for `query`: the one planned for this tick while it holds,
+    // otherwise a cached one when the drone is already walking it,
+    // otherwise a fresh search.
+    fn find_route(&mut self, query: &RouteQuery) -> Option<Path> {
+        match self.plans.get(&self.world, query) {
+            Planned::Route(route) => {
+                // Paths planned before the world last changed are only
+                // good for this tick; drones sharing a plan cache it once
+                if !route.cached
+                    && query.is_cacheable()
+                    && self.plans.is_current(&self.world)
+                    && query.cached(&self.world, &self.paths).is_none()
+                {
+                    let path = route.path.clone();
+                    self.paths
+                        .insert(&self.world, route.target, query.reach(), path);
+                }
+                return Some(route.path.clone());
+            }
+            Planned::Unreachable => return None,
+            Planned::Unknown => {}
+        }
+        if let Some((_, path)) = query.cached(&self.world, &self.paths) {
+            return Some(path);
+        }
+        if query.is_long() {
+            self.chunks.update(&self.world);
+        }
+        let (target, path) = query.search(&self.world, &self.chunks)?;
+        if query.is_cacheable() {
+            self.paths
+                .insert(&self.world, target, query.reach(), path.clone());
+        }
+        Some(path)
+    }
+
+    // The path search drone `idx` is likely to make this tick, going by
+    // the same choices as `step_drone`, so that it can be planned along
+    // with everyone else's. A wrong guess only means searching on the
+    // drone's turn.
+    fn expected_route(&self, idx: usize) -> Option<RouteQuery> {
+        let drone = &self.drones[idx];
+        let position = drone.position;
+        if drone.status == DroneStatus::Broken {
+            return None;
+        }
+        if drone.status == DroneStatus::LowPower || drone.battery.is_low() {
+            let on_charger = self.world.get_tile(position) == Some(TileKind::Charger);
+            let chargers = self.world.chargers();
+            return (!on_charger).then(|| RouteQuery::nearest(position, chargers.to_vec(), 0));
+        }
+        let task = drone.current_task.as_ref();
+        if !drone.cargo.is_empty() {
+            let gathering =
+                task.is_some_and(|t| t.gathers() && !t.work_tiles(&self.world).is_empty());
+            let sites = self.world.drop_off_tiles();
+            if (!gathering || drone.cargo.is_full()) && !sites.is_empty() {
+                let there = sites.iter().any(|s| position.is_within(*s, 1));
+                return (!there).then(|| RouteQuery::nearest(position, sites, 1));
+            }
+        }
+        match task? {
+            Task::MoveTo(dest) => {
+                (!position.is_within(*dest, 0)).then(|| RouteQuery::travel(position, *dest, 0))
+            }
+            Task::Patrol(waypoints) => {
+                let goal = waypoints[drone.waypoint.checked_rem(waypoints.len())?];
+                (!position.is_within(goal, 0)).then(|| RouteQuery::travel(position, goal, 0))
+            }
+            Task::Repair(target_id) => {
+                let target = self
+                    .drones
+                    .iter()
+                    .find(|d| d.id == *target_id && d.status == DroneStatus::Broken)?;
+                let goal = target.position;
+                (!position.is_within(goal, 1)).then(|| RouteQuery::travel(position, goal, 1))
+            }
+            Task::Guard(area) => (!area.contains(position))
+                .then(|| RouteQuery::nearest(position, area.iter_tiles().collect(), 0)),
+            task => {
+                let tiles = task.work_tiles(&self.world);
+                let in_reach = tiles.iter().any(|c| position.is_within(*c, 1));
+                (!tiles.is_empty() && !in_reach).then(|| RouteQuery::nearest(position, tiles, 1))
+            }
+        }
+    }
+
+    // Finds the paths drones are expected to need this tick, all at once
+    // and spread over threads, before any of them moves.
+    fn plan_routes(&mut self) {
+        let queries: Vec<RouteQuery> = (0..self.drones.len())
+            .into_par_iter()
+            .with_min_len(DRONES_PER_THREAD)
+            .filter_map(|idx| self.expected_route(idx))
+            .collect();
+        if queries.iter().any(RouteQuery::is_long) {
+            self.chunks.update(&self.world);
+        }
+        self.plans = RoutePlans::plan(&self.world, &self.chunks, &self.paths, queries);
+    }
+
+    // Sends drone `idx` to the nearest charger once its battery runs low,
+    // putting its task back in the queue, and recharges it there until
+    // full. Idle drones parked on a charger top up too. Returns true while
+    // the drone is busy recharging.
+    fn step_power(&mut self, idx: usize) -> bool {
+        let drone = &self.drones[idx];
+        let on_charger = self.world.get_tile(drone.position) == Some(TileKind::Charger);
+        if drone.status != DroneStatus::LowPower {
+            if !drone.battery.is_low() {
+                if on_charger && drone.current_task.is_none() {
+                    self.drones[idx].battery.recharge();
+                }
+                return false;
+            }
+            if let Some(id) = drone.current_task_id {
+                let reason = format!("Drone #{} is recharging", drone.id);
+                self.tasks.requeue(id, reason);
+                self.release_drone(idx, id);
+            }
+            self.drones[idx].status = DroneStatus::LowPower;
+        }
+        let drone = &mut self.drones[idx];
+        if on_charger {
+            drone.battery.recharge();
+            if drone.battery.is_full() {
+                drone.status = DroneStatus::Idle;
+            }
+            return true;
+        }
+        let position = drone.position;
+        let chargers = self.world.chargers().to_vec();
+        if let Some(path) = self.find_route(&RouteQuery::nearest(position, chargers, 0)) {
+            self.drones[idx].follow(&path);
+            // Still low power, not just moving
+            self.drones[idx].status = DroneStatus::LowPower;
+        }
+        true
+    }
+
+    // Takes drone `idx`'s cargo to the nearest drone-reachable drop-off
+    // (the core or a stockpile) once it is full, or once it has nothing
+    // left to gather. With nowhere to unload the drone carries on and
+    // overflow stays on the ground. Returns true while delivering.
+    fn step_cargo(&mut self, idx: usize) -> bool {
+        let drone = &self.drones[idx];
+        if drone.cargo.is_empty() {
+            return false;
+        }
+        let gathering = drone
+            .current_task
+            .as_ref()
+            .is_some_and(|t| t.gathers() && !t.work_tiles(&self.world).is_empty());
+        if gathering && !drone.cargo.is_full() {
+            return false;
+        }
+        let position = drone.position;
+        let sites = self.world.drop_off_tiles();
+        if let Some(&site) = sites.iter().find(|s| position.is_within(**s, 1)) {
+            let drone = &mut self.drones[idx];
+            let items = drone.cargo.unload();
+            drone.status = if drone.current_task.is_some() {
+                DroneStatus::Working
+            } else {
+                DroneStatus::Idle
+            };
+            self.world.drop_items(site, items);
+            return true;
+        }
+        match self.find_route(&RouteQuery::nearest(position, sites, 1)) {
+            Some(path) => {
+                self.drones[idx].follow(&path);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // One tick of work wears drone `idx` down; a worn drone may break down,
+    // stalling its task where it stands until it is repaired. Returns true
+    // if it just broke.
+    fn wear_out(&mut self, idx: usize) -> bool {
+        let drone = &mut self.drones[idx];
+        drone.durability.wear(1);
+        let chance = drone.durability.breakdown_chance();
+        if chance > 0.0 && self.rng.gen_bool(chance) {
+            drone.status = DroneStatus::Broken;
+            return true;
+        }
+        false
+    }
+
+    fn repair(&mut self, idx: usize) {
+        let drone = &mut self.drones[idx];
+        drone.durability.restore();
+        drone.status = if drone.current_task.is_some() {
+            DroneStatus::Working
+        } else {
+            DroneStatus::Idle
+        };
+    }
+
+    // Every wave due this tick strikes the core; drones guarding an area
+    // each hold some of each wave's enemies off.
+    fn step_waves(&mut self) {
+        let tick = self.clock.tick();
+        if self.world.core().is_none() {
+            return;
+        }
+        let guards = self
             .drones
             .iter()
-            .position(|d| matches!(d.status, DroneStatus::Idle | DroneStatus::Finished));
-        if drone_idx.is_none() {
+            .filter(|d| {
+                d.status == DroneStatus::Working && matches!(d.current_task, Some(Task::Guard(_)))
+            })
+            .count() as u32;
+        for (number, wave) in self.waves.iter().enumerate() {
+            if wave.tick != tick {
+                continue;
+            }
+            self.events.push(EngineEvent::EnemySpawned {
+                wave: number + 1,
+                enemies: wave.enemies,
+            });
+            let through = wave.enemies.saturating_sub(guards * ENEMIES_PER_GUARD);
+            self.world.damage_core(through * ENEMY_DAMAGE);
+        }
+    }
+
+    // The core fixes broken drones next to it, as long as there is iron.
+    fn step_core_repairs(&mut self) {
+        let Some(core) = self.world.core() else {
+            return;
+        };
+        for idx in 0..self.drones.len() {
+            let drone = &self.drones[idx];
+            if drone.status == DroneStatus::Broken
+                && drone.position.is_within(core, 1)
+                && self.world.resources.try_spend(&REPAIR_COST)
+            {
+                self.repair(idx);
+            }
+        }
+    }
+
+    // Gathering tasks aren't finished while the drone still has cargo to
+    // take somewhere.
+    fn has_delivery(&self, idx: usize) -> bool {
+        !self.drones[idx].cargo.is_empty() && !self.world.drop_off_tiles().is_empty()
+    }
+
+    // Advances drone `idx` on its current task: travel toward the next
+    // tile that needs work, or work it once in reach.
+    fn step_drone(&mut self, idx: usize) {
+        if self.drones[idx].status == DroneStatus::Broken
+            || self.step_power(idx)
+            || self.step_cargo(idx)
+        {
             return;
         }
-        let idx = drone_idx.unwrap();
+        let drone = &self.drones[idx];
+        let (Some(id), Some(task)) = (drone.current_task_id, drone.current_task.clone()) else {
+            return;
+        };
+        let position = drone.position;
+        match &task {
+            Task::MoveTo(dest) => match self.travel(idx, *dest, 0) {
+                Travel::NoPath => {
+                    let reason = format!("No path to ({},{},{})", dest.x, dest.y, dest.z);
+                    self.tasks.requeue(id, reason);
+                    self.release_drone(idx, id);
+                }
+                _ if self.drones[idx].position == *dest => {
+                    self.tasks.complete(id);
+                    self.release_drone(idx, id);
+                }
+                _ => {}
+            },
+            Task::Patrol(waypoints) => {
+                // Nowhere to go; only `apply` refuses these up front
+                let Some(leg) = self.drones[idx].waypoint.checked_rem(waypoints.len()) else {
+                    self.tasks.complete(id);
+                    self.release_drone(idx, id);
+                    return;
+                };
+                // Unreachable waypoints are skipped rather than stalling
+                if matches!(
+                    self.travel(idx, waypoints[leg], 0),
+                    Travel::Arrived | Travel::NoPath
+                ) {
+                    self.drones[idx].waypoint = (leg + 1) % waypoints.len();
+                }
+            }
+            Task::Repair(target_id) => {
+                let Some(target) = self.drones.iter().position(|d| d.id == *target_id) else {
+                    self.cancel_task(id);
+                    return;
+                };
+                if self.drones[target].status != DroneStatus::Broken {
+                    // Fixed by someone else in the meantime
+                    self.tasks.complete(id);
+                    self.release_drone(idx, id);
+                    return;
+                }
+                match self.travel(idx, self.drones[target].position, 1) {
+                    Travel::Arrived => {
+                        self.drones[idx].status = DroneStatus::Working;
+                        if self.world.resources.try_spend(&REPAIR_COST) {
+                            self.repair(target);
+                            self.tasks.complete(id);
+                        } else {
+                            self.tasks.requeue(id, "Waiting for resources");
+                        }
+                        self.release_drone(idx, id);
+                    }
+                    Travel::Moving => {}
+                    Travel::NoPath => {
+                        self.tasks
+                            .requeue(id, format!("No path to drone #{}", target_id));
+                        self.release_drone(idx, id);
+                    }
+                }
+            }
+            Task::Guard(area) => {
+                if area.contains(position) {
+                    self.drones[idx].status = DroneStatus::Working;
+                    return;
+                }
+                let tiles: Vec<_> = area.iter_tiles().collect();
+                if let Some(path) = self.find_route(&RouteQuery::nearest(position, tiles, 0)) {
+                    self.drones[idx].follow(&path);
+                }
+            }
+            _ => {
+                let tiles = task.work_tiles(&self.world);
+                if tiles.is_empty() {
+                    self.tasks.complete(id);
+                    self.release_drone(idx, id);
+                    return;
+                }
+                let in_reach = tiles
+                    .iter()
+                    .filter(|c| position.is_within(**c, 1))
+                    .min_by_key(|c| c.manhattan(position));
+                let Some(&target) = in_reach else {
+                    match self.find_route(&RouteQuery::nearest(position, tiles, 1)) {
+                        Some(path) => self.drones[idx].follow(&path),
+                        None => {
+                            self.tasks.requeue(id, "No path to the work area");
+                            self.release_drone(idx, id);
+                        }
+                    }
+                    return;
+                };
+                let drone = &mut self.drones[idx];
+                drone.status = DroneStatus::Working;
+                drone.battery.drain(drone.battery.work_cost);
+                if self.wear_out(idx) {
+                    return;
+                }
+                let drone = &mut self.drones[idx];
+                let power = drone.mining_power;
+                if !apply_task_at(&mut self.world, &task, target, &mut drone.cargo, power) {
+                    let reason = match task {
+                        Task::MineBox(_) => format!("Drone #{} can't dig", drone.id),
+                        // Pause until the stockpile can pay for more tiles
+                        _ => "Waiting for resources".to_string(),
+                    };
+                    self.tasks.requeue(id, reason);
+                    self.release_drone(idx, id);
+                } else if task.is_satisfied(&self.world) && !self.has_delivery(idx) {
+                    self.tasks.complete(id);
+                    self.release_drone(idx, id);
+                }
+            }
+        }
+    }
 
-        // Start a task if any
-        if self.tasks.any_pending() {
-            let next = self.tasks.start_next();
-            if let Some(task) = next {
-                self.drones[idx].status = DroneStatus::Thinking;
-                self.drones[idx].current_task = Some(task.clone());
-                // In Milestone 1 we immediately execute
-                self.drones[idx].status = DroneStatus::Working;
-                let _tiles = apply_task(&mut self.world, &task);
-                self.tasks.complete_current(&task);
-                self.drones[idx].status = DroneStatus::Finished;
-                self.drones[idx].current_task = None;
-                // Reset to Idle for next frame
-                self.drones[idx].status = DroneStatus::Idle;
+    // The core builds one queued drone at a time, in queue order: it pays
+    // when it starts on an order and the drone rolls out at the core
+    // `build_time` ticks later with the next free id.
+    fn step_fabrication(&mut self) {
+        if let Some((id, left)) = self.fabricating {
+            if left > 1 {
+                self.fabricating = Some((id, left - 1));
+                return;
             }
+            self.fabricating = None;
+            let archetype = match self.tasks.get(id) {
+                Some(Task::Fabricate(name)) => find_archetype(&self.archetypes, name).cloned(),
+                _ => None,
+            };
+            match (archetype, self.world.core()) {
+                (Some(a), Some(core)) => {
+                    let next_id = self.drones.iter().map(|d| d.id).max().unwrap_or(0) + 1;
+                    self.drones.push(Drone::of_type(next_id, &a).at(core));
+                    self.tasks.complete(id);
+                }
+                // Nowhere to roll out: the order goes back in the queue and
+                // is paid for again once there is a core
+                (Some(a), None) => {
+                    self.world.resources.add(&a.cost);
+                    self.tasks.requeue(id, "No core to fabricate at");
+                }
+                (None, _) => self.tasks.complete(id),
+            }
+        }
+        let orders: Vec<TaskId> = self
+            .tasks
+            .pending_ids()
+            .into_iter()
+            .filter(|id| matches!(self.tasks.get(*id), Some(Task::Fabricate(_))))
+            .collect();
+        let Some((&first, rest)) = orders.split_first() else {
+            return;
+        };
+        for id in rest {
+            self.tasks
+                .set_blocked(*id, format!("Queued behind task #{}", first));
+        }
+        let Some(Task::Fabricate(name)) = self.tasks.get(first) else {
+            return;
+        };
+        let Some(a) = find_archetype(&self.archetypes, name) else {
+            let reason = format!("Unknown drone type: {}", name);
+            self.tasks.set_blocked(first, reason);
+            return;
+        };
+        if self.world.core().is_none() {
+            self.tasks.set_blocked(first, "No core to fabricate at");
+            return;
+        }
+        let (cost, build_time) = (a.cost, a.build_time);
+        if !self.world.resources.try_spend(&cost) {
+            self.tasks.set_blocked(first, "Waiting for resources");
+            return;
         }
+        self.tasks.start(first);
+        self.fabricating = Some((first, build_time.max(1)));
+    }
+
+    // Everything that happened since the last call, oldest first.
+    pub fn drain_events(&mut self) -> Vec<EngineEvent> {
+        self.events.drain()
+    }
+
+    // Feeds `seconds` of real time to the clock and runs the ticks that
+    // are due at the current speed. Returns how many ran.
+    // Nothing runs before the game is started or after it is over.
+    pub fn advance(&mut self, seconds: f64) -> u32 {
+        if self.session.state() != SessionState::Running {
+            return 0;
+        }
+        let due = self.clock.advance(seconds);
+        for _ in 0..due {
+            self.tick();
+        }
+        due
+    }
+
+    // Runs one tick, starting the game if it is still being set up. Does
+    // nothing once the game is over.
+    pub fn tick(&mut self) {
+        if self.session.state().is_over() {
+            return;
+        }
+        self.session.start();
+        if self.determinism_check {
+            let mut shadow = Engine::from_save(self.to_save());
+            shadow.step();
+            self.step();
+            let (ours, theirs) = (self.state_hashes(), shadow.state_hashes());
+            assert!(
+                ours == theirs,
+                "Tick {} isn't deterministic: {} differ",
+                ours.tick,
+                ours.diff(&theirs).join(", ")
+            );
+        } else {
+            self.step();
+        }
+        let tick = self.clock.tick();
+        if self
+            .recording
+            .as_ref()
+            .is_some_and(|r| tick.is_multiple_of(r.hash_interval))
+        {
+            let hash = self.state_hash();
+            if let Some(recording) = &mut self.recording {
+                recording.checkpoints.push(Checkpoint { tick, hash });
+            }
+        }
+        if self
+            .history
+            .as_ref()
+            .is_some_and(|h| tick.is_multiple_of(h.interval()))
+        {
+            self.take_snapshot();
+        }
+    }
+
+    // Processes a single step:
+    // - Walk pending tasks in queue order and pick a free drone for each
+    // - Tasks that can't start stay Pending with the reason recorded
+    // - Each drone starts at most one task per tick; low batteries start none
+    // - Plan: find every drone's path at once, in parallel, against the
+    //   world as it is before any of them acts
+    // - Apply: drones take turns in order, so the world changes the same
+    //   way however many threads planned; a plan the world has since cut
+    //   off is searched for again on the drone's turn
+    // - Busy drones then move `speed` tiles or work one tile of their task
+    // - Drones running low drop their task and head for a charger
+    // - Standing tasks (patrol, guard) keep their drone busy until cancelled
+    // - Fabrication orders are built by the core, never by drones
+    // - Work wears drones down until they break and wait for a repair
+    fn step(&mut self) {
+        self.clock.record_tick();
+        self.step_waves();
+        self.step_scripts();
+        self.step_fabrication();
+        self.step_core_repairs();
+        let mut free: Vec<usize> = (0..self.drones.len())
+            .filter(|&i| {
+                let drone = &self.drones[i];
+                matches!(drone.status, DroneStatus::Idle | DroneStatus::Finished)
+                    && !drone.battery.is_low()
+            })
+            .collect();
+        for id in self.tasks.pending_ids() {
+            let Some(task) = self.tasks.get(id).cloned() else {
+                continue;
+            };
+            if let Task::Fabricate(_) = task {
+                continue;
+            }
+            if !task.is_ready(&self.world) {
+                self.tasks.set_blocked(id, "Waiting for resources");
+                continue;
+            }
+            let slot = match self.pick_drone(id, &task, &free) {
+                Ok(slot) => slot,
+                Err(reason) => {
+                    self.tasks.set_blocked(id, reason);
+                    continue;
+                }
+            };
+            if let Err(conflicts) = self.reservations.try_claim(id, &task.footprint()) {
+                self.tasks.set_blocked(
+                    id,
+                    format!("Area reserved by task #{}", conflicts[0].held_by),
+                );
+                continue;
+            }
+            let idx = free.remove(slot);
+            if self.tasks.start(id).is_some() {
+                self.start_on_drone(idx, id, task);
+            }
+        }
+        self.plan_routes();
+        for idx in 0..self.drones.len() {
+            self.step_drone(idx);
+        }
+        self.plans.clear();
+        let survived = self.waves_survived();
+        let (core_hp, _) = self.world.core_hp();
+        self.session.check(core_hp, survived, &self.world.resources);
+        self.events.collect(
+            &mut self.world,
+            &self.drones,
+            &self.tasks,
+            self.session.state(),
+        );
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::coords::{TileBox3, TileCoord3};
-    use crate::tasks::Task;
+    use crate::archetypes::Archetype;
+    use crate::coords::TileBox3;
+    use crate::history::{DEFAULT_HISTORY_BUDGET, DEFAULT_SNAPSHOT_INTERVAL};
+    use crate::resources::Resources;
+    use crate::script::ScriptStatus;
     use crate::tile::TileKind;
 
+    fn run(engine: &mut Engine, ticks: usize) {
+        for _ in 0..ticks {
+            engine.tick();
+        }
+    }
+
     #[test]
     fn engine_executes_task() {
-        let world = World::new(2, 2, 1, TileKind::Stone);
+        let mut world = World::new(3, 2, 1, TileKind::Stone);
+        let spot = TileBox3::new(TileCoord3::new(2, 0, 0), TileCoord3::new(2, 1, 0));
+        for c in spot.iter_tiles() {
+            world.set_tile(c, TileKind::Floor);
+        }
+        world.add_stockpile(spot);
         let mut engine = Engine::new(
             world,
             vec![Drone {
                 id: 1,
                 status: DroneStatus::Idle,
                 current_task: None,
+                current_task_id: None,
+                ..Drone::new(1)
             }],
         );
         let t = Task::MineBox(TileBox3::new(
             TileCoord3::new(0, 0, 0),
             TileCoord3::new(1, 1, 0),
         ));
+        let area = t.area().unwrap();
         engine.tasks.push(t);
-        engine.tick();
+        engine.tasks.push(Task::Haul(area));
+        // One tile per tick, plus one step into the dug-out space to reach
+        // the far corner; all four fit in the drone's cargo
+        run(&mut engine, 5);
+        assert_eq!(engine.drones[0].cargo.items.stone, 4);
+        assert_eq!(engine.tasks.state(0), Some(TaskState::InProgress));
+        // Unloaded from next to the stockpile, then the task is done
+        run(&mut engine, 2);
+        assert_eq!(engine.tasks.state(0), Some(TaskState::Done));
         assert_eq!(engine.world.resources.stone, 4);
+        assert!(engine.world.ground_total().is_empty());
+        // Nothing was left lying around, so the haul has nothing to do
+        run(&mut engine, 1);
+        assert_eq!(engine.tasks.state(1), Some(TaskState::Done));
+    }
+
+    #[test]
+    fn queue_reports_overlap_and_cancel_releases() {
+        let mut world = World::new(4, 4, 1, TileKind::Stone);
+        for x in 0..4 {
+            world.set_tile(TileCoord3::new(x, 0, 0), TileKind::Air);
+        }
+        let mut engine = Engine::new(world, vec![Drone::new(1)]);
+        let a = Task::MineBox(TileBox3::new(
+            TileCoord3::new(0, 0, 0),
+            TileCoord3::new(1, 1, 0),
+        ));
+        let b = Task::MineBox(TileBox3::new(
+            TileCoord3::new(1, 1, 0),
+            TileCoord3::new(2, 2, 0),
+        ));
+        let (first, conflicts) = engine.queue_task(a);
+        assert!(conflicts.is_empty());
+        let (second, conflicts) = engine.queue_task(b);
+        assert_eq!(
+            conflicts,
+            vec![ReservationConflict {
+                tile: TileCoord3::new(1, 1, 0),
+                held_by: first,
+            }]
+        );
+
+        assert!(engine.cancel_task(first));
+        run(&mut engine, 10);
+        assert_eq!(engine.tasks.state(second), Some(TaskState::Done));
+        assert!(engine.reservations.is_empty());
+        // No stockpile to unload at, so the drone keeps what it mined
+        assert_eq!(engine.drones[0].cargo.items.stone, 4);
+    }
+
+    #[test]
+    fn reserved_tiles_block_other_tasks() {
+        let world = World::new(2, 1, 1, TileKind::Stone);
+        let mut engine = Engine::new(world, vec![Drone::new(1)]);
+        let t = Task::MineBox(TileBox3::new(
+            TileCoord3::new(0, 0, 0),
+            TileCoord3::new(1, 0, 0),
+        ));
+        let (id, _) = engine.queue_task(t);
+        // Someone else already holds one of the tiles
+        engine
+            .reservations
+            .try_claim(99, &[TileCoord3::new(1, 0, 0)])
+            .unwrap();
+        engine.tick();
+        assert_eq!(engine.tasks.state(id), Some(TaskState::Pending));
+        engine.reservations.release(99);
+        run(&mut engine, 2);
+        assert_eq!(engine.tasks.state(id), Some(TaskState::Done));
+    }
+
+    #[test]
+    fn build_task_pauses_until_mining_pays_for_it() {
+        let mut world = World::new(4, 1, 1, TileKind::Air);
+        let quarry = TileCoord3::new(3, 0, 0);
+        world.set_tile(quarry, TileKind::Stone);
+        // Mined right on the stockpile, so no hauling is needed
+        world.add_stockpile(TileBox3::new(quarry, quarry));
+        let mut engine = Engine::new(world, vec![Drone::new(1)]);
+        let (build, _) = engine.queue_task(Task::BuildFloor(TileBox3::new(
+            TileCoord3::new(0, 0, 0),
+            TileCoord3::new(0, 0, 0),
+        )));
+        let (mine, _) = engine.queue_task(Task::MineBox(TileBox3::new(
+            TileCoord3::new(3, 0, 0),
+            TileCoord3::new(3, 0, 0),
+        )));
+        // Nothing to pay with yet, so the mining task runs first: two
+        // ticks to fly next to the quarry, one to mine it, one to unload
+        // and one to finish
+        run(&mut engine, 5);
+        assert_eq!(engine.tasks.state(build), Some(TaskState::Pending));
+        assert_eq!(engine.tasks.state(mine), Some(TaskState::Done));
+        run(&mut engine, 2);
+        assert_eq!(engine.tasks.state(build), Some(TaskState::Done));
+        assert_eq!(
+            engine.world.get_tile(TileCoord3::new(0, 0, 0)),
+            Some(TileKind::Floor)
+        );
+        assert_eq!(engine.world.resources.stone, 0);
+    }
+
+    #[test]
+    fn patrol_occupies_addressed_drone_until_cancelled() {
+        let world = World::new(4, 4, 1, TileKind::Stone);
+        let mut engine = Engine::new(world, vec![Drone::new(1), Drone::new(3)]);
+        let patrol = Task::Patrol(vec![TileCoord3::new(0, 0, 0), TileCoord3::new(3, 0, 0)]);
+        let (patrol_id, _) = engine.queue_task_for(patrol, 3);
+        engine.tick();
+        // Drone 1 was idle first but the patrol is addressed to drone 3
+        assert_eq!(engine.drones[0].status, DroneStatus::Idle);
+        assert_eq!(engine.drones[1].status, DroneStatus::Working);
+        assert_eq!(engine.drones[1].current_task_id, Some(patrol_id));
+
+        let (mine, _) = engine.queue_task(Task::MineBox(TileBox3::new(
+            TileCoord3::new(0, 0, 0),
+            TileCoord3::new(0, 0, 0),
+        )));
+        for _ in 0..3 {
+            engine.tick();
+        }
+        assert_eq!(engine.tasks.state(mine), Some(TaskState::Done));
+        assert_eq!(engine.tasks.state(patrol_id), Some(TaskState::InProgress));
+
+        assert!(engine.cancel_task(patrol_id));
+        assert_eq!(engine.drones[1].status, DroneStatus::Idle);
+        assert!(engine.drones[1].current_task.is_none());
+    }
+
+    #[test]
+    fn patrol_commands_need_two_waypoints() {
+        let world = World::new(4, 4, 1, TileKind::Air);
+        let mut engine = Engine::new(world, vec![Drone::new(1)]);
+        let patrol = |waypoints| Command::QueueTask {
+            task: Task::Patrol(waypoints),
+            drone: None,
+        };
+        for waypoints in [Vec::new(), vec![TileCoord3::new(1, 1, 0)]] {
+            assert!(matches!(
+                engine.apply(patrol(waypoints)),
+                Err(CommandError::Compile(CompileError::ShortPatrol))
+            ));
+        }
+        assert!(engine.tasks.tasks.is_empty());
+    }
+
+    #[test]
+    fn patrol_without_waypoints_is_finished_at_once() {
+        let world = World::new(4, 4, 1, TileKind::Air);
+        let mut engine = Engine::new(world, vec![Drone::new(1)]);
+        let (patrol, _) = engine.queue_task(Task::Patrol(Vec::new()));
+        run(&mut engine, 2);
+        assert_eq!(engine.tasks.state(patrol), Some(TaskState::Done));
+        assert!(engine.drones[0].current_task.is_none());
+    }
+
+    #[test]
+    fn explicit_assignment_and_affinity() {
+        let world = World::new(4, 1, 1, TileKind::Stone);
+        let mut miner = Drone::new(1);
+        miner.affinity = Some(TaskKind::Mine);
+        let mut engine = Engine::new(world, vec![Drone::new(2), miner]);
+        let tile = |x| TileBox3::new(TileCoord3::new(x, 0, 0), TileCoord3::new(x, 0, 0));
+
+        // The mining drone is listed second but gets the mining task
+        let (first, _) = engine.queue_task(Task::MineBox(tile(0)));
+        // Explicitly bound to drone 2
+        let (second, _) = engine.queue_task(Task::MineBox(tile(1)));
+        assert_eq!(engine.assign_task(second, 2), Ok(()));
+        assert_eq!(
+            engine.assign_task(second, 9),
+            Err(AssignError::UnknownDrone(9))
+        );
+        // Bound to a drone that doesn't exist: stays pending with a reason
+        let (third, _) = engine.queue_task_for(Task::MineBox(tile(2)), 7);
+
+        engine.tick();
+        assert_eq!(engine.tasks.state(first), Some(TaskState::Done));
+        assert_eq!(engine.tasks.state(second), Some(TaskState::Done));
+        assert_eq!(engine.tasks.state(third), Some(TaskState::Pending));
+        assert_eq!(
+            engine.tasks.blocked_reason(third),
+            Some("Drone #7 does not exist")
+        );
+        assert_eq!(
+            engine.assign_task(first, 1),
+            Err(AssignError::NotPending(first))
+        );
+    }
+
+    #[test]
+    fn program_bound_to_drone() {
+        let world = World::new(2, 1, 1, TileKind::Stone);
+        let mut engine = Engine::new(world, vec![Drone::new(1), Drone::new(2)]);
+        let program: Program = serde_json::from_value(serde_json::json!({
+            "version": 1,
+            "node": "Program",
+            "drone": 2,
+            "statements": [{
+                "node": "ExprStmt",
+                "expr": {
+                    "node": "Call",
+                    "func": "guard",
+                    "args": [{
+                        "node": "TileBoxFromCoords",
+                        "min": { "node": "TileCoord", "x": 0, "y": 0 },
+                        "max": { "node": "TileCoord", "x": 1, "y": 0 }
+                    }]
+                }
+            }]
+        }))
+        .unwrap();
+        let queued = engine.queue_program(&program).unwrap();
+        assert_eq!(engine.tasks.assigned_drone(queued[0].0), Some(2));
+        engine.tick();
+        assert_eq!(engine.drones[0].status, DroneStatus::Idle);
+        assert_eq!(engine.drones[1].status, DroneStatus::Working);
+    }
+
+    #[test]
+    fn drone_travels_before_working() {
+        let mut world = World::new(6, 1, 1, TileKind::Air);
+        let far = TileCoord3::new(5, 0, 0);
+        world.set_tile(far, TileKind::Stone);
+        let mut engine = Engine::new(world, vec![Drone::new(1)]);
+        let (id, _) = engine.queue_task(Task::MineBox(TileBox3::new(far, far)));
+        // Four moves to get next to the tile
+        run(&mut engine, 4);
+        assert_eq!(engine.drones[0].status, DroneStatus::Moving);
+        assert_eq!(engine.drones[0].position, TileCoord3::new(4, 0, 0));
+        assert_eq!(engine.world.get_tile(far), Some(TileKind::Stone));
+        engine.tick();
+        assert_eq!(engine.world.get_tile(far), Some(TileKind::Air));
+        assert_eq!(engine.tasks.state(id), Some(TaskState::Done));
+
+        let (go, _) = engine.queue_task(Task::MoveTo(TileCoord3::new(0, 0, 0)));
+        engine.drones[0].speed = 2;
+        run(&mut engine, 2);
+        assert_eq!(engine.tasks.state(go), Some(TaskState::Done));
+        assert_eq!(engine.drones[0].position, TileCoord3::new(0, 0, 0));
+        assert_eq!(engine.drones[0].status, DroneStatus::Idle);
+    }
+
+    #[test]
+    fn low_battery_returns_to_charger_then_resumes() {
+        let mut world = World::new(8, 1, 1, TileKind::Air);
+        world.set_tile(TileCoord3::new(0, 0, 0), TileKind::Charger);
+        let mut drone = Drone::new(1);
+        drone.battery = crate::drones::Battery::new(20);
+        drone.battery.charge = 6;
+        let mut engine = Engine::new(world, vec![drone]);
+        let dest = TileCoord3::new(5, 0, 0);
+        let (id, _) = engine.queue_task(Task::MoveTo(dest));
+
+        // One step out drains the battery to a quarter
+        run(&mut engine, 2);
+        assert_eq!(engine.drones[0].status, DroneStatus::LowPower);
+        assert_eq!(engine.drones[0].position, TileCoord3::new(0, 0, 0));
+        assert_eq!(engine.tasks.state(id), Some(TaskState::Pending));
+        assert_eq!(
+            engine.tasks.blocked_reason(id),
+            Some("Drone #1 is recharging")
+        );
+        assert!(
+            crate::hud::format_side_panel(&engine.drones, &engine.tasks)
+                .iter()
+                .any(|l| l.contains("Low power"))
+        );
+
+        // Two ticks on the charger fill it up, then the trip resumes
+        run(&mut engine, 2);
+        assert_eq!(engine.drones[0].status, DroneStatus::Idle);
+        assert!(engine.drones[0].battery.is_full());
+        run(&mut engine, 5);
+        assert_eq!(engine.tasks.state(id), Some(TaskState::Done));
+        assert_eq!(engine.drones[0].position, dest);
+    }
+
+    #[test]
+    fn full_cargo_is_unloaded_before_mining_more() {
+        let mut world = World::new(6, 1, 1, TileKind::Stone);
+        let spot = TileCoord3::new(0, 0, 0);
+        world.set_tile(spot, TileKind::Floor);
+        world.add_stockpile(TileBox3::new(spot, spot));
+        let mut drone = Drone::new(1).at(TileCoord3::new(1, 0, 0));
+        drone.cargo = crate::drones::Cargo::new(2);
+        let mut engine = Engine::new(world, vec![drone]);
+        let (id, _) = engine.queue_task(Task::MineBox(TileBox3::new(
+            TileCoord3::new(1, 0, 0),
+            TileCoord3::new(5, 0, 0),
+        )));
+        // Two tiles fill the cargo; the drone heads back to the stockpile
+        // before mining the third
+        run(&mut engine, 2);
+        assert!(engine.drones[0].cargo.is_full());
+        run(&mut engine, 2);
+        assert_eq!(engine.world.resources.stone, 2);
+        assert_eq!(
+            engine.world.get_tile(TileCoord3::new(3, 0, 0)),
+            Some(TileKind::Stone)
+        );
+        run(&mut engine, 20);
+        assert_eq!(engine.tasks.state(id), Some(TaskState::Done));
+        assert_eq!(engine.world.resources.stone, 5);
+        assert!(engine.world.ground_total().is_empty());
+    }
+
+    #[test]
+    fn scheduler_respects_drone_types() {
+        let world = World::new(4, 1, 1, TileKind::Stone);
+        let scout = Drone::of_type(1, &Archetype::scout());
+        let miner = Drone::of_type(2, &Archetype::miner());
+        let mut engine = Engine::new(world, vec![scout, miner]);
+        let tile = |x| TileBox3::new(TileCoord3::new(x, 0, 0), TileCoord3::new(x, 0, 0));
+
+        engine.world.resources.add_stone(2);
+        // The scout is free first but can't mine
+        let (mine, _) = engine.queue_task(Task::MineBox(tile(0)));
+        let (build, _) = engine.queue_task(Task::BuildWall(tile(3)));
+        assert_eq!(
+            engine.assign_task(mine, 1),
+            Err(AssignError::Incapable(1, TaskKind::Mine))
+        );
+        engine.tick();
+        assert_eq!(engine.tasks.state(mine), Some(TaskState::Done));
+        assert_eq!(engine.drones[1].cargo.items.stone, 1);
+        assert_eq!(engine.drones[0].status, DroneStatus::Idle);
+        assert_eq!(
+            engine.tasks.blocked_reason(build),
+            Some("No drone can do building")
+        );
+    }
+
+    #[test]
+    fn core_fabricates_queued_drones_in_order() {
+        let mut world = World::new(3, 3, 1, TileKind::Air);
+        let core = TileCoord3::new(1, 1, 0);
+        world.set_core(core);
+        world.resources = crate::resources::Resources { stone: 8, iron: 6 };
+        let mut engine = Engine::new(world, vec![Drone::new(1)]);
+        assert_eq!(
+            engine.fabricate("warrior"),
+            Err(AssignError::UnknownDroneType("warrior".to_string()))
+        );
+        let scout = engine.fabricate("scout").unwrap();
+        let miner = engine.fabricate("Miner").unwrap();
+
+        // The scout is paid for straight away; the miner waits its turn
+        engine.tick();
+        assert_eq!(engine.world.resources.stone, 6);
+        assert_eq!(engine.fabrication_progress(), Some((scout, 10)));
+        assert_eq!(
+            engine.tasks.blocked_reason(miner),
+            Some("Queued behind task #0")
+        );
+        run(&mut engine, 10);
+        assert_eq!(engine.tasks.state(scout), Some(TaskState::Done));
+        assert_eq!(engine.drones.len(), 2);
+        assert_eq!(engine.drones[1].id, 2);
+        assert_eq!(engine.drones[1].type_name(), Some("Scout"));
+        assert_eq!(engine.drones[1].position, core);
+
+        // The miner started the same tick; cancelling refunds it
+        assert_eq!(engine.tasks.state(miner), Some(TaskState::InProgress));
+        assert_eq!(engine.world.resources.stone, 0);
+        assert!(engine.cancel_task(miner));
+        assert_eq!(engine.world.resources.stone, 6);
+        assert_eq!(engine.world.resources.iron, 4);
+        assert_eq!(engine.fabrication_progress(), None);
+    }
+
+    #[test]
+    fn fabrication_waits_for_a_core_to_roll_out_at() {
+        let mut world = World::new(3, 3, 1, TileKind::Air);
+        world.set_core(TileCoord3::new(1, 1, 0));
+        world.resources = Resources { stone: 2, iron: 2 };
+        let mut engine = Engine::new(world, vec![Drone::new(1)]);
+        let scout = engine.fabricate("scout").unwrap();
+        engine.tick();
+        assert_eq!(engine.world.resources, Resources { stone: 0, iron: 0 });
+
+        // The core is gone by the time the scout is built
+        let mut coreless = World::new(3, 3, 1, TileKind::Air);
+        coreless.resources = engine.world.resources;
+        engine.world = coreless;
+        run(&mut engine, 10);
+        assert_eq!(engine.drones.len(), 1);
+        assert_eq!(engine.tasks.state(scout), Some(TaskState::Pending));
+        assert_eq!(
+            engine.tasks.blocked_reason(scout),
+            Some("No core to fabricate at")
+        );
+        assert_eq!(engine.world.resources, Resources { stone: 2, iron: 2 });
+    }
+
+    #[test]
+    fn mining_time_depends_on_hardness_and_power() {
+        let mut world = World::new(3, 1, 1, TileKind::Iron);
+        world.set_tile(TileCoord3::new(1, 0, 0), TileKind::Air);
+        let base = Drone::new(1).at(TileCoord3::new(1, 0, 0));
+        let miner = Drone::of_type(2, &Archetype::miner()).at(TileCoord3::new(1, 0, 0));
+        let mut engine = Engine::new(world, vec![base, miner]);
+        let tile = |x| TileBox3::new(TileCoord3::new(x, 0, 0), TileCoord3::new(x, 0, 0));
+        let (slow, _) = engine.queue_task_for(Task::MineBox(tile(0)), 1);
+        let (fast, _) = engine.queue_task_for(Task::MineBox(tile(2)), 2);
+
+        engine.tick();
+        // The miner breaks iron in one go; the base drone is halfway there
+        assert_eq!(engine.tasks.state(fast), Some(TaskState::Done));
+        assert_eq!(engine.tasks.state(slow), Some(TaskState::InProgress));
+        assert_eq!(engine.world.damage_at(TileCoord3::new(0, 0, 0)), 2);
+        engine.tick();
+        assert_eq!(engine.tasks.state(slow), Some(TaskState::Done));
+        assert_eq!(engine.drones[0].cargo.items.iron, 1);
+    }
+
+    #[test]
+    fn program_waits_for_another_drone_to_finish_the_shaft() {
+        let mut world = World::new(6, 1, 1, TileKind::Air);
+        for x in 3..5 {
+            world.set_tile(TileCoord3::new(x, 0, 0), TileKind::Stone);
+        }
+        let drones = vec![
+            Drone::new(1).at(TileCoord3::new(2, 0, 0)),
+            Drone::new(2).at(TileCoord3::new(0, 0, 0)),
+        ];
+        let mut engine = Engine::new(world, drones);
+        let program = |drone: u32, statements| -> Program {
+            serde_json::from_value(serde_json::json!({
+                "version": 1, "node": "Program", "drone": drone, "statements": statements
+            }))
+            .unwrap()
+        };
+        let call = |func: &str, arg| {
+            serde_json::json!({
+                "node": "ExprStmt",
+                "expr": { "node": "Call", "func": func, "args": [arg] }
+            })
+        };
+        let shaft = serde_json::json!({
+            "node": "TileBoxFromCoords",
+            "min": { "node": "TileCoord", "x": 3, "y": 0 },
+            "max": { "node": "TileCoord", "x": 4, "y": 0 }
+        });
+        let flag = serde_json::json!({ "node": "StringLiteral", "value": "shaft_done" });
+        let digger = engine
+            .run_program(program(
+                1,
+                serde_json::json!([call("mine_box", shaft), call("set_flag", flag.clone())]),
+            ))
+            .unwrap();
+        let waiter = engine
+            .run_program(program(
+                2,
+                serde_json::json!([
+                    call("wait_for", flag),
+                    call(
+                        "move_to",
+                        serde_json::json!({ "node": "TileCoord", "x": 5, "y": 0 })
+                    )
+                ]),
+            ))
+            .unwrap();
+
+        engine.tick();
+        let mine = engine.scripts[digger].current_task().unwrap();
+        // Drone 2 queues nothing while the shaft is still being dug
+        while engine.tasks.state(mine) != Some(TaskState::Done) {
+            assert_eq!(engine.tasks.tasks.len(), 1);
+            assert_eq!(
+                engine.scripts[waiter].status,
+                ScriptStatus::Waiting("Waiting for shaft_done".into())
+            );
+            engine.tick();
+        }
+        run(&mut engine, 8);
+        assert!(engine.blackboard.is_set("shaft_done"));
+        assert_eq!(engine.scripts[digger].status, ScriptStatus::Done);
+        assert_eq!(engine.scripts[waiter].status, ScriptStatus::Done);
+        assert_eq!(engine.drones[1].position, TileCoord3::new(5, 0, 0));
+    }
+
+    #[test]
+    fn simulation_speed_does_not_depend_on_frame_rate() {
+        let make = || {
+            let world = World::new(20, 1, 1, TileKind::Air);
+            let mut engine = Engine::new(world, vec![Drone::new(1)]);
+            engine.queue_task(Task::MoveTo(TileCoord3::new(19, 0, 0)));
+            engine.start();
+            engine
+        };
+        let mut smooth = make();
+        let mut choppy = make();
+        for _ in 0..60 {
+            smooth.advance(1.0 / 60.0);
+        }
+        for _ in 0..20 {
+            choppy.advance(1.0 / 20.0);
+        }
+        assert_eq!(smooth.clock.tick(), 10);
+        assert_eq!(choppy.clock.tick(), 10);
+        assert_eq!(smooth.drones[0].position, choppy.drones[0].position);
+
+        smooth.clock.set_speed(crate::clock::GameSpeed::Paused);
+        assert_eq!(smooth.advance(1.0), 0);
+        smooth.clock.step_once();
+        assert_eq!(smooth.advance(1.0 / 60.0), 1);
+        assert_eq!(smooth.clock.tick(), 11);
+    }
+
+    #[test]
+    fn tick_reports_what_happened() {
+        let mut world = World::new(3, 1, 1, TileKind::Air);
+        let rock = TileCoord3::new(2, 0, 0);
+        world.set_tile(rock, TileKind::Stone);
+        world.set_core(TileCoord3::new(0, 0, 0));
+        let mut engine = Engine::new(world, vec![Drone::new(1).at(TileCoord3::new(1, 0, 0))]);
+        assert!(engine.drain_events().is_empty());
+        let (mine, _) = engine.queue_task(Task::MineBox(TileBox3::new(rock, rock)));
+
+        engine.tick();
+        let events = engine.drain_events();
+        assert!(events.contains(&EngineEvent::TaskStarted {
+            task: mine,
+            drone: Some(1)
+        }));
+        assert!(events.contains(&EngineEvent::TileChanged {
+            pos: rock,
+            kind: TileKind::Air
+        }));
+        assert!(events.contains(&EngineEvent::DroneStatusChanged {
+            drone: 1,
+            from: DroneStatus::Idle,
+            to: DroneStatus::Working
+        }));
+
+        run(&mut engine, 3);
+        let events = engine.drain_events();
+        assert!(events.contains(&EngineEvent::ResourceGained(Resources {
+            stone: 1,
+            iron: 0
+        })));
+        assert!(events.contains(&EngineEvent::TaskCompleted { task: mine }));
+        assert!(engine.drain_events().is_empty());
+    }
+
+    #[test]
+    fn waves_wear_the_core_down_until_the_game_is_lost() {
+        let mut world = World::new(5, 5, 1, TileKind::Floor);
+        world.set_core(TileCoord3::new(2, 2, 0));
+        let mut engine = Engine::new(world, vec![Drone::new(1)]);
+        engine.waves = vec![
+            Wave {
+                tick: 2,
+                enemies: 12,
+            },
+            Wave {
+                tick: 4,
+                enemies: 22,
+            },
+        ];
+        let post = TileCoord3::new(0, 0, 0);
+        engine.queue_task(Task::Guard(TileBox3::new(post, post)));
+        assert_eq!(engine.session_state(), SessionState::Setup);
+        assert_eq!(engine.advance(1.0), 0);
+
+        // The guard holds two enemies off each wave
+        run(&mut engine, 2);
+        assert_eq!(engine.session_state(), SessionState::Running);
+        assert_eq!(engine.world.core_hp(), (50, 100));
+        let events = engine.drain_events();
+        assert!(events.contains(&EngineEvent::EnemySpawned {
+            wave: 1,
+            enemies: 12
+        }));
+        assert!(events.contains(&EngineEvent::CoreDamaged { hp: 50, max: 100 }));
+        run(&mut engine, 2);
+        assert_eq!(engine.world.core_hp(), (0, 100));
+        assert_eq!(engine.session_state(), SessionState::Lost);
+        assert_eq!(engine.waves_survived(), 1);
+        assert!(
+            engine
+                .drain_events()
+                .contains(&EngineEvent::GameOver(SessionState::Lost))
+        );
+
+        // Nothing moves once the game is over
+        run(&mut engine, 5);
+        assert_eq!(engine.clock.tick(), 4);
+    }
+
+    #[test]
+    fn waves_due_on_the_same_tick_all_strike() {
+        let mut world = World::new(5, 5, 1, TileKind::Floor);
+        world.set_core(TileCoord3::new(2, 2, 0));
+        let mut engine = Engine::new(world, vec![Drone::new(1)]);
+        let wave = |tick, enemies| Wave { tick, enemies };
+        engine.waves = vec![wave(2, 4), wave(2, 6), wave(3, 12)];
+        run(&mut engine, 2);
+        assert_eq!(engine.world.core_hp(), (50, 100));
+        assert_eq!(engine.waves_survived(), 2);
+        let spawned = engine
+            .drain_events()
+            .into_iter()
+            .filter(|e| matches!(e, EngineEvent::EnemySpawned { .. }))
+            .count();
+        assert_eq!(spawned, 2);
+
+        // Lost to the next one
+        engine.waves.push(wave(3, 1));
+        engine.tick();
+        assert_eq!(engine.session_state(), SessionState::Lost);
+        assert_eq!(engine.waves_survived(), 2);
+    }
+
+    #[test]
+    fn undoing_a_guard_can_lose_the_game_earlier() {
+        let mut world = World::new(5, 5, 1, TileKind::Floor);
+        world.set_core(TileCoord3::new(2, 2, 0));
+        let mut engine = Engine::new(world, vec![Drone::new(1)]);
+        engine.waves = vec![
+            Wave {
+                tick: 2,
+                enemies: 12,
+            },
+            Wave {
+                tick: 4,
+                enemies: 8,
+            },
+        ];
+        engine.enable_history(DEFAULT_SNAPSHOT_INTERVAL, DEFAULT_HISTORY_BUDGET);
+        let post = TileCoord3::new(0, 0, 0);
+        let guard = Command::QueueTask {
+            task: Task::Guard(TileBox3::new(post, post)),
+            drone: None,
+        };
+        engine.apply(guard).unwrap();
+        run(&mut engine, 6);
+        assert_eq!(engine.session_state(), SessionState::Running);
+
+        // Without the guard the second wave destroys the core
+        assert!(matches!(engine.undo(), Ok(Command::QueueTask { .. })));
+        assert_eq!(engine.session_state(), SessionState::Lost);
+        assert_eq!(engine.clock.tick(), 4);
+    }
+
+    #[test]
+    fn cached_paths_never_change_where_drones_go() {
+        let mut world = World::new(12, 6, 1, TileKind::Air);
+        let (far, near) = (TileCoord3::new(10, 0, 0), TileCoord3::new(7, 2, 0));
+        world.set_tile(far, TileKind::Charger);
+        world.set_tile(near, TileKind::Charger);
+        let sitter = Drone::new(2).at(TileCoord3::new(7, 0, 0));
+        let mut engine = Engine::new(world, vec![Drone::new(1), sitter]);
+        engine.queue_task_for(Task::MoveTo(far), 1);
+        engine.tick();
+        // Drone 1's cached path to the far charger runs through drone 2,
+        // which now needs the nearest one
+        let battery = &mut engine.drones[1].battery;
+        battery.charge = battery.capacity / 5;
+        for _ in 0..3 {
+            // A copy rebuilt from a save starts with no cached paths
+            let mut copy = Engine::from_save(engine.to_save());
+            copy.tick();
+            engine.tick();
+            assert_eq!(engine.state_hashes(), copy.state_hashes());
+        }
+        assert_eq!(engine.drones[1].position, near);
+    }
+
+    #[test]
+    fn worn_drone_breaks_down_until_repaired() {
+        let mut world = World::new(6, 1, 1, TileKind::Air);
+        let quarry = TileCoord3::new(5, 0, 0);
+        world.set_tile(quarry, TileKind::Stone);
+        world.set_core(TileCoord3::new(0, 0, 0));
+        world.resources.add_iron(4);
+        let mut worn = Drone::new(1).at(TileCoord3::new(4, 0, 0));
+        worn.durability.current = 0;
+        // Already broken, but parked next to the core
+        let mut mechanic = Drone::new(2).at(TileCoord3::new(1, 0, 0));
+        mechanic.status = DroneStatus::Broken;
+        let mut engine = Engine::new(world, vec![worn, mechanic]);
+        let (mine, _) = engine.queue_task_for(Task::MineBox(TileBox3::new(quarry, quarry)), 1);
+
+        // The core fixes drone 2; drone 1 breaks on its first tick of work
+        engine.tick();
+        assert_eq!(engine.drones[1].status, DroneStatus::Idle);
+        assert_eq!(engine.drones[0].status, DroneStatus::Broken);
+        assert_eq!(engine.tasks.state(mine), Some(TaskState::InProgress));
+        assert_eq!(engine.world.get_tile(quarry), Some(TileKind::Stone));
+        assert_eq!(engine.world.resources.iron, 2);
+
+        // Drone 2 flies over and fixes it for two more iron: two moves to
+        // get next to it, then the repair
+        let (repair, _) = engine.queue_task(Task::Repair(1));
+        run(&mut engine, 3);
+        assert_eq!(engine.tasks.state(repair), Some(TaskState::Done));
+        assert_eq!(engine.world.resources.iron, 0);
+        assert_eq!(engine.drones[0].durability.current, 300);
+        // Back at work; the stone then goes home to the core
+        run(&mut engine, 1);
+        assert_eq!(engine.world.get_tile(quarry), Some(TileKind::Air));
+        assert_eq!(engine.drones[0].cargo.items.stone, 1);
     }
 }
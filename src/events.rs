@@ -0,0 +1,183 @@
+use std::collections::{HashMap, VecDeque};
+
+use serde::Serialize;
+
+use crate::coords::TileCoord3;
+use crate::drones::{Drone, DroneStatus};
+use crate::resources::Resources;
+use crate::session::SessionState;
+use crate::tasks::{TaskId, TaskManager, TaskState};
+use crate::tile::TileKind;
+use crate::world::World;
+
+// Events nobody drains are dropped oldest first past this many.
+pub const MAX_QUEUED_EVENTS: usize = 4096;
+
+// Something that happened during a tick, for the GUI, logs and tests.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub enum EngineEvent {
+    // Kind changed or the tile was partly mined
+    TileChanged {
+        pos: TileCoord3,
+        kind: TileKind,
+    },
+    ResourceGained(Resources),
+    ResourceSpent(Resources),
+    TaskStarted {
+        task: TaskId,
+        drone: Option<u32>,
+    },
+    TaskCompleted {
+        task: TaskId,
+    },
+    // Gave up and went back to the queue, e.g. no path or low battery
+    TaskFailed {
+        task: TaskId,
+        reason: String,
+    },
+    TaskCancelled {
+        task: TaskId,
+    },
+    DroneStatusChanged {
+        drone: u32,
+        from: DroneStatus,
+        to: DroneStatus,
+    },
+    DroneFabricated {
+        drone: u32,
+    },
+    // Wave `wave`, numbered from 1, reached the core
+    EnemySpawned {
+        wave: usize,
+        enemies: u32,
+    },
+    CoreDamaged {
+        hp: u32,
+        max: u32,
+    },
+    // Won or lost
+    GameOver(SessionState),
+}
+
+// Turns the difference between two ticks into events. Comparing against
+// what was last reported keeps the engine's many state changes free of
+// bookkeeping, and picks up edits made between ticks too. The catch is
+// that only where things ended up is seen: a task that fails and is picked
+// up again within one tick reports no `TaskFailed`, and a drone whose
+// status flips and flips back reports nothing. Waves, which leave no state
+// of their own behind, are reported by the engine as they strike.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct EventLog {
+    queue: VecDeque<EngineEvent>,
+    resources: Resources,
+    core_hp: u32,
+    tasks: Vec<TaskState>,
+    drones: HashMap<u32, DroneStatus>,
+    session: SessionState,
+}
+
+impl EventLog {
+    // Takes the current state as seen, without reporting anything.
+    pub(crate) fn new(
+        world: &mut World,
+        drones: &[Drone],
+        tasks: &TaskManager,
+        session: SessionState,
+    ) -> Self {
+        let mut log = Self::default();
+        log.collect(world, drones, tasks, session);
+        log.queue.clear();
+        log
+    }
+
+    pub(crate) fn drain(&mut self) -> Vec<EngineEvent> {
+        self.queue.drain(..).collect()
+    }
+
+    pub(crate) fn push(&mut self, event: EngineEvent) {
+        if self.queue.len() == MAX_QUEUED_EVENTS {
+            self.queue.pop_front();
+        }
+        self.queue.push_back(event);
+    }
+
+    pub(crate) fn collect(
+        &mut self,
+        world: &mut World,
+        drones: &[Drone],
+        tasks: &TaskManager,
+        session: SessionState,
+    ) {
+        for pos in world.take_edits() {
+            if let Some(kind) = world.get_tile(pos) {
+                self.push(EngineEvent::TileChanged { pos, kind });
+            }
+        }
+
+        let now = world.resources;
+        let gained = Resources {
+            stone: now.stone.saturating_sub(self.resources.stone),
+            iron: now.iron.saturating_sub(self.resources.iron),
+        };
+        let spent = Resources {
+            stone: self.resources.stone.saturating_sub(now.stone),
+            iron: self.resources.iron.saturating_sub(now.iron),
+        };
+        if gained.total() > 0 {
+            self.push(EngineEvent::ResourceGained(gained));
+        }
+        if spent.total() > 0 {
+            self.push(EngineEvent::ResourceSpent(spent));
+        }
+        self.resources = now;
+
+        let (hp, max) = world.core_hp();
+        if hp < self.core_hp {
+            self.push(EngineEvent::CoreDamaged { hp, max });
+        }
+        self.core_hp = hp;
+
+        for (id, (_, state)) in tasks.tasks.iter().enumerate() {
+            let before = self.tasks.get(id).copied();
+            if before == Some(*state) {
+                continue;
+            }
+            let event = match (before, *state) {
+                (_, TaskState::InProgress) => EngineEvent::TaskStarted {
+                    task: id,
+                    drone: drones
+                        .iter()
+                        .find(|d| d.current_task_id == Some(id))
+                        .map(|d| d.id),
+                },
+                (_, TaskState::Done) => EngineEvent::TaskCompleted { task: id },
+                (_, TaskState::Cancelled) => EngineEvent::TaskCancelled { task: id },
+                (Some(TaskState::InProgress), TaskState::Pending) => EngineEvent::TaskFailed {
+                    task: id,
+                    reason: tasks.blocked_reason(id).unwrap_or("Requeued").to_string(),
+                },
+                // Newly queued
+                _ => continue,
+            };
+            self.push(event);
+        }
+        self.tasks = tasks.tasks.iter().map(|(_, state)| *state).collect();
+
+        for d in drones {
+            match self.drones.insert(d.id, d.status) {
+                None => self.push(EngineEvent::DroneFabricated { drone: d.id }),
+                Some(from) if from != d.status => self.push(EngineEvent::DroneStatusChanged {
+                    drone: d.id,
+                    from,
+                    to: d.status,
+                }),
+                Some(_) => {}
+            }
+        }
+
+        if session != self.session && session.is_over() {
+            self.push(EngineEvent::GameOver(session));
+        }
+        self.session = session;
+    }
+}
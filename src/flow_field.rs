@@ -0,0 +1,303 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet, VecDeque};
+
+use crate::coords::TileCoord3;
+use crate::tile::TileKind;
+use crate::world::World;
+
+const UNREACHABLE: u32 = u32::MAX;
+
+// Min-heap of (distance, tile key) still to expand.
+type OpenSet = BinaryHeap<Reverse<(u32, (i32, i32, i32))>>;
+
+// Dijkstra map: steps from every tile to the nearest target, e.g. the core.
+// Any number of units can read `next_step` instead of running their own
+// search, and `update` patches the map from the world's change log rather
+// than rebuilding it after every dig.
+#[derive(Debug, Clone)]
+pub struct FlowField {
+    targets: HashSet<TileCoord3>,
+    width: i32,
+    height: i32,
+    levels: i32,
+    dist: Vec<u32>,
+    revision: u64,
+}
+
+impl FlowField {
+    // Targets count as open even when they aren't passable, so a core
+    // building can be the goal.
+    pub fn new(world: &World, targets: &[TileCoord3]) -> Self {
+        let size = (world.width() * world.height() * world.levels()) as usize;
+        let mut field = Self {
+            targets: targets.iter().copied().collect(),
+            width: world.width(),
+            height: world.height(),
+            levels: world.levels(),
+            dist: vec![UNREACHABLE; size],
+            revision: world.revision(),
+        };
+        field.rebuild(world);
+        field
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    // Steps to the nearest target, or None if it can't be reached.
+    pub fn distance(&self, c: TileCoord3) -> Option<u32> {
+        let d = self.dist[self.index(c)?];
+        (d != UNREACHABLE).then_some(d)
+    }
+
+    // Where a unit at `c` should go next; None once on a target or when
+    // no target can be reached.
+    pub fn next_step(&self, world: &World, c: TileCoord3) -> Option<TileCoord3> {
+        let here = self.distance(c).unwrap_or(UNREACHABLE);
+        self.links(world, c)
+            .into_iter()
+            .filter_map(|n| Some((self.distance(n)?, n)))
+            .filter(|(d, _)| *d < here)
+            .min_by_key(|(d, n)| (*d, n.z, n.y, n.x))
+            .map(|(_, n)| n)
+    }
+
+    // Catches up with tiles changed since the last update, repairing only
+    // the part of the map routed through them.
+    pub fn update(&mut self, world: &World) {
+        if self.revision == world.revision() {
+            return;
+        }
+        match world.changes_since(self.revision) {
+            Some(changes) => {
+                for &c in changes {
+                    self.repair(world, c);
+                }
+            }
+            None => self.rebuild(world),
+        }
+        self.revision = world.revision();
+    }
+
+    pub fn rebuild(&mut self, world: &World) {
+        self.dist.fill(UNREACHABLE);
+        let mut open = BinaryHeap::new();
+        for t in self.targets.clone() {
+            if let Some(i) = self.index(t) {
+                self.dist[i] = 0;
+                open.push(Reverse((0, key(t))));
+            }
+        }
+        self.propagate(world, open);
+        self.revision = world.revision();
+    }
+
+    fn repair(&mut self, world: &World, c: TileCoord3) {
+        if self.index(c).is_none() {
+            return;
+        }
+        // Everything whose distance may have been routed through `c`: the
+        // tiles reachable from it by steps that each add one
+        let mut affected = vec![c];
+        let mut seen: HashSet<_> = affected.iter().copied().collect();
+        let mut queue = VecDeque::from([c]);
+        while let Some(cur) = queue.pop_front() {
+            let Some(d) = self.distance(cur) else {
+                continue;
+            };
+            for n in adjacent(cur) {
+                if self.distance(n) == Some(d + 1) && seen.insert(n) {
+                    affected.push(n);
+                    queue.push_back(n);
+                }
+            }
+        }
+        for a in &affected {
+            if let Some(i) = self.index(*a) {
+                self.dist[i] = UNREACHABLE;
+            }
+        }
+        // Reseed from the untouched tiles around them and let the changes
+        // flow outwards; a newly opened tile can shorten routes anywhere
+        let mut open = BinaryHeap::new();
+        for a in affected {
+            let d = if self.targets.contains(&a) {
+                0
+            } else if !self.is_open(world, a) {
+                continue;
+            } else {
+                match self
+                    .links(world, a)
+                    .into_iter()
+                    .filter_map(|n| self.distance(n))
+                    .min()
+                {
+                    Some(d) => d + 1,
+                    None => continue,
+                }
+            };
+            if let Some(i) = self.index(a) {
+                self.dist[i] = d;
+                open.push(Reverse((d, key(a))));
+            }
+        }
+        self.propagate(world, open);
+    }
+
+    fn propagate(&mut self, world: &World, mut open: OpenSet) {
+        while let Some(Reverse((d, (z, y, x)))) = open.pop() {
+            let c = TileCoord3::new(x, y, z);
+            if self.distance(c).is_some_and(|best| d > best) {
+                continue;
+            }
+            for n in self.links(world, c) {
+                let Some(i) = self.index(n) else {
+                    continue;
+                };
+                if d + 1 < self.dist[i] {
+                    self.dist[i] = d + 1;
+                    open.push(Reverse((d + 1, key(n))));
+                }
+            }
+        }
+    }
+
+    fn is_open(&self, world: &World, c: TileCoord3) -> bool {
+        self.targets.contains(&c) || world.is_passable(c)
+    }
+
+    // Same moves as `pathfinding::neighbors`, with targets treated as open.
+    fn links(&self, world: &World, c: TileCoord3) -> Vec<TileCoord3> {
+        let mut out = Vec::with_capacity(6);
+        for (dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+            let n = TileCoord3::new(c.x + dx, c.y + dy, c.z);
+            if self.is_open(world, n) {
+                out.push(n);
+            }
+        }
+        if world.get_tile(c) == Some(TileKind::Stairs) {
+            for dz in [1, -1] {
+                let n = TileCoord3::new(c.x, c.y, c.z + dz);
+                if world.get_tile(n) == Some(TileKind::Stairs) {
+                    out.push(n);
+                }
+            }
+        }
+        out
+    }
+
+    fn index(&self, c: TileCoord3) -> Option<usize> {
+        if c.x < 0
+            || c.y < 0
+            || c.z < 0
+            || c.x >= self.width
+            || c.y >= self.height
+            || c.z >= self.levels
+        {
+            return None;
+        }
+        Some(((c.z * self.height + c.y) * self.width + c.x) as usize)
+    }
+}
+
+fn key(c: TileCoord3) -> (i32, i32, i32) {
+    (c.z, c.y, c.x)
+}
+
+// All six face neighbours, whether or not a unit could step there.
+fn adjacent(c: TileCoord3) -> [TileCoord3; 6] {
+    [
+        TileCoord3::new(c.x + 1, c.y, c.z),
+        TileCoord3::new(c.x - 1, c.y, c.z),
+        TileCoord3::new(c.x, c.y + 1, c.z),
+        TileCoord3::new(c.x, c.y - 1, c.z),
+        TileCoord3::new(c.x, c.y, c.z + 1),
+        TileCoord3::new(c.x, c.y, c.z - 1),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pathfinding::find_path;
+    use rand::{Rng, SeedableRng, rngs::StdRng};
+
+    fn assert_same(a: &FlowField, b: &FlowField, world: &World) {
+        for z in 0..world.levels() {
+            for y in 0..world.height() {
+                for x in 0..world.width() {
+                    let c = TileCoord3::new(x, y, z);
+                    assert_eq!(a.distance(c), b.distance(c), "at {:?}", c);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn distances_match_astar_and_lead_to_target() {
+        let mut world = World::new(8, 8, 1, TileKind::Air);
+        for y in 0..7 {
+            world.set_tile(TileCoord3::new(4, y, 0), TileKind::Stone);
+        }
+        // The core itself is a solid block
+        let core = TileCoord3::new(7, 0, 0);
+        world.set_tile(core, TileKind::Wall);
+        let field = FlowField::new(&world, &[core]);
+        assert_eq!(field.distance(core), Some(0));
+
+        let start = TileCoord3::new(0, 0, 0);
+        let beside = TileCoord3::new(6, 0, 0);
+        let astar = find_path(&world, start, beside).unwrap();
+        assert_eq!(field.distance(start), Some(astar.len() as u32 + 1));
+
+        let mut at = start;
+        let mut steps = 0;
+        while let Some(next) = field.next_step(&world, at) {
+            assert!(next == core || world.is_passable(next));
+            at = next;
+            steps += 1;
+        }
+        assert_eq!(at, core);
+        assert_eq!(steps, field.distance(start).unwrap());
+        assert_eq!(field.distance(TileCoord3::new(4, 0, 0)), None);
+    }
+
+    #[test]
+    fn incremental_updates_match_rebuild() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut world = World::new(16, 16, 2, TileKind::Air);
+        for z in 0..2 {
+            for y in 0..16 {
+                for x in 0..16 {
+                    if rng.gen_bool(0.3) {
+                        world.set_tile(TileCoord3::new(x, y, z), TileKind::Stone);
+                    }
+                }
+            }
+        }
+        world.set_tile(TileCoord3::new(8, 8, 0), TileKind::Stairs);
+        world.set_tile(TileCoord3::new(8, 8, 1), TileKind::Stairs);
+        let core = TileCoord3::new(0, 0, 0);
+        let mut field = FlowField::new(&world, &[core]);
+        for _ in 0..40 {
+            // A few digs, walls and stairs between each update
+            for _ in 0..3 {
+                let c = TileCoord3::new(
+                    rng.gen_range(0..16),
+                    rng.gen_range(0..16),
+                    rng.gen_range(0..2),
+                );
+                let kind = match rng.gen_range(0..4) {
+                    0 => TileKind::Wall,
+                    1 => TileKind::Stairs,
+                    _ => TileKind::Air,
+                };
+                world.set_tile(c, kind);
+            }
+            field.update(&world);
+            assert_eq!(field.revision(), world.revision());
+            assert_same(&field, &FlowField::new(&world, &[core]), &world);
+        }
+    }
+}
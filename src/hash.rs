@@ -0,0 +1,125 @@
+use std::io;
+
+use serde::Serialize;
+
+// FNV-1a fed through `io::Write`, so values can be hashed by streaming
+// their save encoding into it. Save encodings keep every map sorted, which
+// makes the hash independent of HashMap order, platform and build.
+#[derive(Debug, Clone, Copy)]
+pub struct StateHasher(u64);
+
+impl Default for StateHasher {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl StateHasher {
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+
+    pub fn write_u64(&mut self, n: u64) {
+        self.write_bytes(&n.to_le_bytes());
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 = (self.0 ^ u64::from(*b)).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+impl io::Write for StateHasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_bytes(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+pub fn hash_of<T: Serialize>(value: &T) -> u64 {
+    let mut hasher = StateHasher::default();
+    ciborium::into_writer(value, &mut hasher).expect("game state always encodes");
+    hasher.finish()
+}
+
+// Hashes of each part of the game, so a mismatch says where two runs
+// split, not just that they did.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StateHashes {
+    pub tick: u64,
+    pub world: u64,
+    pub drones: u64,
+    // Queue, reservations and the fabrication in progress
+    pub tasks: u64,
+    pub rng: u64,
+    // Running scripts and the blackboard
+    pub programs: u64,
+    // Drone types and waves
+    pub setup: u64,
+}
+
+impl StateHashes {
+    pub fn combined(&self) -> u64 {
+        let mut hasher = StateHasher::default();
+        for part in [
+            self.tick,
+            self.world,
+            self.drones,
+            self.tasks,
+            self.rng,
+            self.programs,
+            self.setup,
+        ] {
+            hasher.write_u64(part);
+        }
+        hasher.finish()
+    }
+
+    // Names of the parts that differ.
+    pub fn diff(&self, other: &StateHashes) -> Vec<&'static str> {
+        [
+            ("tick", self.tick == other.tick),
+            ("world", self.world == other.world),
+            ("drones", self.drones == other.drones),
+            ("tasks", self.tasks == other.tasks),
+            ("rng", self.rng == other.rng),
+            ("programs", self.programs == other.programs),
+            ("setup", self.setup == other.setup),
+        ]
+        .into_iter()
+        .filter(|(_, same)| !same)
+        .map(|(name, _)| name)
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coords::TileCoord3;
+    use crate::reservations::Reservations;
+    use crate::tile::TileKind;
+    use crate::world::World;
+
+    #[test]
+    fn hash_ignores_insertion_order() {
+        let tiles: Vec<_> = (0..50).map(|i| TileCoord3::new(i % 7, i / 7, 0)).collect();
+        let mut a = Reservations::new();
+        let mut b = Reservations::new();
+        assert!(a.try_claim(1, &tiles).is_ok());
+        let reversed: Vec<_> = tiles.iter().rev().copied().collect();
+        assert!(b.try_claim(1, &reversed).is_ok());
+        assert_eq!(hash_of(&a), hash_of(&b));
+
+        let mut w1 = World::new(4, 4, 1, TileKind::Stone);
+        let w2 = w1.clone();
+        assert_eq!(w1.state_hash(), w2.state_hash());
+        w1.set_tile(TileCoord3::new(1, 1, 0), TileKind::Air);
+        assert_ne!(w1.state_hash(), w2.state_hash());
+    }
+}
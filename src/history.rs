@@ -0,0 +1,184 @@
+use std::collections::VecDeque;
+
+use thiserror::Error;
+
+use crate::command::CommandRecord;
+use crate::save::{SaveError, SaveGame, decode};
+use crate::tile::TileKind;
+use crate::world::World;
+
+// Ticks between snapshots. Rolling back lands on the snapshot before the
+// target and replays the rest, so this bounds how much is re-simulated.
+pub const DEFAULT_SNAPSHOT_INTERVAL: u64 = 20;
+
+// Memory kept for snapshots before the oldest ones are dropped.
+pub const DEFAULT_HISTORY_BUDGET: usize = 16 << 20;
+
+#[derive(Debug, Error)]
+pub enum HistoryError {
+    #[error("History is turned off")]
+    Disabled,
+    #[error("Tick {0} is no longer in the history")]
+    TooOld(u64),
+    #[error("Tick {0} hasn't happened yet")]
+    InFuture(u64),
+    #[error("Nothing to undo")]
+    NothingToUndo,
+    #[error("Nothing to redo")]
+    NothingToRedo,
+    #[error(transparent)]
+    Save(#[from] SaveError),
+}
+
+// The engine at one tick, before that tick's commands. Copying the tile
+// grid each time would be too costly on big maps, so the state is saved
+// without it; instead each snapshot keeps the tiles overwritten until the
+// next one, and rolling back undoes those newest first.
+#[derive(Debug, Clone)]
+pub(crate) struct Snapshot {
+    pub(crate) tick: u64,
+    // Binary save with an empty tile grid
+    state: Vec<u8>,
+    // Old kinds of the tiles changed after this snapshot, oldest first
+    tiles: Vec<(u32, TileKind)>,
+}
+
+impl Snapshot {
+    pub(crate) fn new(tick: u64, state: Vec<u8>) -> Self {
+        Self {
+            tick,
+            state,
+            tiles: Vec::new(),
+        }
+    }
+
+    fn size(&self) -> usize {
+        self.state.len() + self.tiles.len() * size_of::<(u32, TileKind)>()
+    }
+}
+
+// Snapshots and the player commands given since the oldest one, for
+// rolling back and for undo/redo.
+#[derive(Debug, Clone)]
+pub struct History {
+    interval: u64,
+    budget: usize,
+    snapshots: VecDeque<Snapshot>,
+    used: usize,
+    // Commands that succeeded, oldest first
+    pub(crate) commands: Vec<CommandRecord>,
+    // Commands taken back by `Engine::undo`, most recent last
+    pub(crate) undone: Vec<CommandRecord>,
+}
+
+impl History {
+    pub fn new(interval: u64, budget: usize) -> Self {
+        Self {
+            interval: interval.max(1),
+            budget,
+            snapshots: VecDeque::new(),
+            used: 0,
+            commands: Vec::new(),
+            undone: Vec::new(),
+        }
+    }
+
+    pub fn interval(&self) -> u64 {
+        self.interval
+    }
+
+    // The earliest tick that can still be restored.
+    pub fn oldest_tick(&self) -> Option<u64> {
+        self.snapshots.front().map(|s| s.tick)
+    }
+
+    pub fn memory_used(&self) -> usize {
+        self.used
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.commands.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.undone.is_empty()
+    }
+
+    // Adds a snapshot, handing the previous one the tiles changed since,
+    // and drops the oldest ones past the memory budget.
+    pub(crate) fn push(&mut self, snapshot: Snapshot, changed: Vec<(u32, TileKind)>) {
+        if let Some(last) = self.snapshots.back_mut() {
+            self.used += changed.len() * size_of::<(u32, TileKind)>();
+            last.tiles = changed;
+        }
+        self.used += snapshot.size();
+        self.snapshots.push_back(snapshot);
+        while self.used > self.budget && self.snapshots.len() > 1 {
+            if let Some(dropped) = self.snapshots.pop_front() {
+                self.used -= dropped.size();
+            }
+        }
+        let oldest = self.oldest_tick().unwrap_or(0);
+        self.commands.retain(|c| c.tick >= oldest);
+    }
+
+    // Rolls `world`'s tile grid back to the newest snapshot at or before
+    // `tick`, forgetting everything after it. Returns that snapshot's state,
+    // with the grid moved in, and the commands given since.
+    pub(crate) fn roll_back(
+        &mut self,
+        tick: u64,
+        world: &mut World,
+    ) -> Result<(SaveGame, Vec<CommandRecord>), HistoryError> {
+        let k = self
+            .snapshots
+            .iter()
+            .rposition(|s| s.tick <= tick)
+            .ok_or(HistoryError::TooOld(tick))?;
+        let mut save: SaveGame = decode(&self.snapshots[k].state)?;
+        let live = world.take_journal();
+        world.undo_journal(&live);
+        for s in self.snapshots.range(k..).rev() {
+            world.undo_journal(&s.tiles);
+        }
+        save.world.take_tiles_from(world);
+
+        for dropped in self.snapshots.drain(k + 1..) {
+            self.used -= dropped.size();
+        }
+        let kept = &mut self.snapshots[k];
+        self.used -= kept.size();
+        kept.tiles.clear();
+        self.used += kept.size();
+
+        let from = self.commands.partition_point(|c| c.tick < kept.tick);
+        Ok((save, self.commands.split_off(from)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::Command;
+
+    fn record(tick: u64) -> CommandRecord {
+        CommandRecord {
+            tick,
+            command: Command::CancelTask(0),
+        }
+    }
+
+    #[test]
+    fn oldest_snapshots_go_first_past_the_budget() {
+        let mut history = History::new(10, 250);
+        for tick in [0, 10, 20, 30] {
+            history.commands.push(record(tick));
+            history.push(Snapshot::new(tick, vec![0; 100]), Vec::new());
+        }
+        assert_eq!(history.oldest_tick(), Some(20));
+        assert!(history.memory_used() <= 250);
+        // Commands from before the oldest snapshot can't be undone any more
+        let ticks: Vec<_> = history.commands.iter().map(|c| c.tick).collect();
+        assert_eq!(ticks, [20, 30]);
+    }
+}
@@ -1,6 +1,9 @@
-use crate::drones::{Drone, DroneStatus};
+use crate::clock::SimClock;
+use crate::drones::{Cargo, Drone, DroneStatus};
 use crate::resources::Resources;
-use crate::tasks::{TaskManager, TaskState};
+use crate::scenario::Wave;
+use crate::session::VictoryCondition;
+use crate::tasks::{TaskId, TaskManager, TaskState};
 
 pub const HUD_SEPARATOR: &str = " • ";
 pub const HUD_Z_UP_LABEL: &str = "Z▲";
@@ -22,31 +25,102 @@ pub fn format_hud(resources: &Resources, wave_label: &str, core_hp: (u32, u32))
     )
 }
 
+// Countdown to the next wave in game time, e.g. "Wave 2 in 01:30".
+pub fn format_wave_label(next: Option<(usize, &Wave)>, clock: &SimClock) -> String {
+    match next {
+        Some((n, wave)) => {
+            let secs = wave.tick.saturating_sub(clock.tick()) / u64::from(clock.tick_rate());
+            format!("Wave {} in {:02}:{:02}", n, secs / 60, secs % 60)
+        }
+        None => "No waves left".to_string(),
+    }
+}
+
+// e.g. "Win by: survive 3 waves or collect 200 iron".
+pub fn format_objectives(victory: &[VictoryCondition]) -> String {
+    if victory.is_empty() {
+        return "Hold out as long as you can".to_string();
+    }
+    let goals: Vec<_> = victory.iter().map(|v| v.describe()).collect();
+    format!("Win by: {}", goals.join(" or "))
+}
+
+// e.g. "Cargo 3/10: 2 stone, 1 iron".
+pub fn format_cargo(cargo: &Cargo) -> String {
+    let mut kinds = Vec::new();
+    if cargo.items.stone > 0 {
+        kinds.push(format!("{} stone", cargo.items.stone));
+    }
+    if cargo.items.iron > 0 {
+        kinds.push(format!("{} iron", cargo.items.iron));
+    }
+    if kinds.is_empty() {
+        kinds.push("empty".to_string());
+    }
+    format!(
+        "Cargo {}/{}: {}",
+        cargo.used(),
+        cargo.capacity,
+        kinds.join(", ")
+    )
+}
+
+// Drone currently running the task, or the one it is assigned to.
+pub fn task_holder(drones: &[Drone], tasks: &TaskManager, id: TaskId) -> Option<u32> {
+    drones
+        .iter()
+        .find(|d| d.current_task_id == Some(id))
+        .map(|d| d.id)
+        .or_else(|| tasks.assigned_drone(id))
+}
+
 pub fn format_side_panel(drones: &[Drone], tasks: &TaskManager) -> Vec<String> {
     let mut out = Vec::new();
     out.push("[Drones]".to_string());
     for d in drones {
         let status = match d.status {
-            DroneStatus::Idle => "Idle",
-            DroneStatus::Thinking => "Thinking...",
-            DroneStatus::Working => "Working",
-            DroneStatus::Finished => "Finished",
+            DroneStatus::Idle => "Idle".to_string(),
+            DroneStatus::Thinking => "Thinking...".to_string(),
+            DroneStatus::Moving => "Moving".to_string(),
+            DroneStatus::Working => "Working".to_string(),
+            DroneStatus::Finished => "Finished".to_string(),
+            DroneStatus::LowPower => format!("Low power ({}%)", d.battery.percent()),
+            DroneStatus::Broken => "Broken".to_string(),
         };
         let task = d
             .current_task
             .as_ref()
             .map(|t| t.description())
             .unwrap_or_else(|| "None".to_string());
-        out.push(format!("Drone #{} - {} - {}", d.id, status, task));
+        let mut line = format!("Drone #{}", d.id);
+        if let Some(name) = d.type_name() {
+            line.push_str(&format!(" ({})", name));
+        }
+        if let Some(k) = d.affinity {
+            line.push_str(&format!(" [{}]", k.label()));
+        }
+        line.push_str(&format!(" - {} - {}", status, task));
+        if !d.cargo.is_empty() {
+            line.push_str(&format!(" - {}", format_cargo(&d.cargo)));
+        }
+        out.push(line);
     }
     out.push("[Tasks]".to_string());
-    for (t, s) in &tasks.tasks {
+    for (id, (t, s)) in tasks.tasks.iter().enumerate() {
         let state = match s {
             TaskState::Pending => "Pending",
             TaskState::InProgress => "InProgress",
             TaskState::Done => "Done",
+            TaskState::Cancelled => "Cancelled",
         };
-        out.push(format!("{} - {}", t.description(), state));
+        let mut line = format!("#{} {} - {}", id, t.description(), state);
+        if let Some(reason) = tasks.blocked_reason(id) {
+            line.push_str(&format!(" ({})", reason));
+        }
+        if let Some(drone_id) = task_holder(drones, tasks, id) {
+            line.push_str(&format!(" - Drone #{}", drone_id));
+        }
+        out.push(line);
     }
     out
 }
@@ -67,6 +141,26 @@ mod tests {
         assert!(s.contains("Wave 1 in 01:23"));
         assert!(s.contains("Core HP: 90/100"));
         assert!(s.contains(HUD_SEPARATOR));
+
+        let wave = Wave {
+            tick: 830,
+            enemies: 4,
+        };
+        let clock = SimClock::new(10);
+        assert_eq!(
+            format_wave_label(Some((1, &wave)), &clock),
+            "Wave 1 in 01:23"
+        );
+        assert_eq!(format_wave_label(None, &clock), "No waves left");
+
+        let victory = [
+            VictoryCondition::SurviveWaves { waves: 3 },
+            VictoryCondition::CollectIron { amount: 200 },
+        ];
+        assert_eq!(
+            format_objectives(&victory),
+            "Win by: survive 3 waves or collect 200 iron"
+        );
     }
 
     #[test]
@@ -88,9 +182,40 @@ mod tests {
             id: 1,
             status: DroneStatus::Idle,
             current_task: Some(t),
+            current_task_id: Some(0),
+            ..Drone::new(1)
         }];
+        let mut drones = drones;
+        drones[0].cargo.load(Resources { stone: 2, iron: 1 });
         let lines = format_side_panel(&drones, &tasks);
         assert!(lines.iter().any(|l| l.contains("Drone #1")));
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.ends_with("Cargo 3/10: 2 stone, 1 iron"))
+        );
         assert!(lines.iter().any(|l| l.contains("Tasks")));
     }
+
+    #[test]
+    fn side_panel_shows_assignment_and_reason() {
+        let mut tasks = TaskManager::new();
+        let id = tasks.push(Task::MineBox(TileBox3::new(
+            TileCoord3::new(0, 0, 0),
+            TileCoord3::new(0, 0, 0),
+        )));
+        tasks.assign(id, 2);
+        tasks.set_blocked(id, "Waiting for drone #2");
+        let mut drone = Drone::new(2);
+        drone.affinity = Some(crate::tasks::TaskKind::Haul);
+        let scout = Drone::of_type(3, &crate::archetypes::Archetype::scout());
+        let lines = format_side_panel(&[drone, scout], &tasks);
+        assert!(lines.contains(&"Drone #2 [Hauling] - Idle - None".to_string()));
+        assert!(lines.contains(&"Drone #3 (Scout) - Idle - None".to_string()));
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.ends_with("Pending (Waiting for drone #2) - Drone #2"))
+        );
+    }
 }
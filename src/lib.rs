@@ -1,20 +1,57 @@
+pub mod archetypes;
+pub mod batch;
+pub mod blackboard;
+pub mod chunk_graph;
+pub mod clock;
+pub mod command;
+pub mod console;
 pub mod coords;
 pub mod drones;
 pub mod dsl_ast;
 pub mod engine;
+pub mod events;
+pub mod flow_field;
+pub mod hash;
+pub mod history;
 pub mod hud;
+pub mod pathfinding;
+mod planning;
+pub mod replay;
+pub mod reservations;
 pub mod resources;
+pub mod save;
+pub mod scenario;
+pub mod script;
+pub mod session;
 pub mod tasks;
 pub mod tile;
 pub mod world;
 
 // Re-exports for convenience in tests and integration users.
+pub use archetypes::{Archetype, builtin_archetypes, find_archetype};
+pub use batch::{BatchError, RunStats};
+pub use blackboard::{Blackboard, Message, Value};
+pub use chunk_graph::ChunkGraph;
+pub use clock::{GameSpeed, SimClock};
+pub use command::{Command, CommandError, CommandOutput, CommandRecord};
+pub use console::{ConsoleCommand, parse_console_command};
 pub use coords::{TileBox3, TileCoord3};
-pub use drones::{Drone, DroneStatus};
+pub use drones::{Battery, Cargo, Drone, DroneStatus};
 pub use dsl_ast::{Program, compile_program_to_tasks};
-pub use engine::Engine;
+pub use engine::{AssignError, Engine};
+pub use events::EngineEvent;
+pub use flow_field::FlowField;
+pub use hash::StateHashes;
+pub use history::{History, HistoryError};
 pub use hud::{format_hud, format_side_panel};
+pub use pathfinding::{Path, PathCache, find_path};
+pub use replay::{Replay, ReplayError};
+pub use reservations::{ReservationConflict, Reservations};
 pub use resources::Resources;
-pub use tasks::{Task, TaskManager, TaskState};
+pub use save::{SaveError, SaveFormat, SaveGame};
+pub use scenario::{Scenario, ScenarioError, Wave};
+pub use script::{Script, ScriptId, ScriptStatus};
+pub use session::{Session, SessionState, VictoryCondition};
+pub use tasks::{Task, TaskId, TaskKind, TaskManager, TaskState};
 pub use tile::TileKind;
 pub use world::World;
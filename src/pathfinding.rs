@@ -0,0 +1,365 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::coords::TileCoord3;
+use crate::tile::TileKind;
+use crate::world::World;
+
+// Above this many targets the A* heuristic costs more than it saves, so the
+// search falls back to plain Dijkstra.
+const MAX_HEURISTIC_TARGETS: usize = 32;
+
+// Tiles from start to end, both included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Path {
+    pub tiles: Vec<TileCoord3>,
+}
+
+impl Path {
+    pub fn start(&self) -> TileCoord3 {
+        self.tiles[0]
+    }
+
+    pub fn end(&self) -> TileCoord3 {
+        self.tiles[self.tiles.len() - 1]
+    }
+
+    // Number of moves along the path.
+    pub fn len(&self) -> usize {
+        self.tiles.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // The rest of the path from `c` on, if the path passes through it.
+    pub fn from_tile(&self, c: TileCoord3) -> Option<Path> {
+        let i = self.tiles.iter().position(|t| *t == c)?;
+        Some(Path {
+            tiles: self.tiles[i..].to_vec(),
+        })
+    }
+}
+
+// Tiles a drone can step to from `c`: the four passable horizontal
+// neighbours, plus the tile above or below when both ends are stairs.
+pub fn neighbors(world: &World, c: TileCoord3) -> Vec<TileCoord3> {
+    let mut out = Vec::with_capacity(6);
+    for (dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+        let n = TileCoord3::new(c.x + dx, c.y + dy, c.z);
+        if world.is_passable(n) {
+            out.push(n);
+        }
+    }
+    if world.get_tile(c) == Some(TileKind::Stairs) {
+        for dz in [1, -1] {
+            let n = TileCoord3::new(c.x, c.y, c.z + dz);
+            if world.get_tile(n) == Some(TileKind::Stairs) {
+                out.push(n);
+            }
+        }
+    }
+    out
+}
+
+pub fn find_path(world: &World, start: TileCoord3, goal: TileCoord3) -> Option<Path> {
+    find_path_within(world, start, goal, 0)
+}
+
+// Path to a passable tile within `reach` of `goal` on the same level, e.g.
+// a tile next to a wall that needs mining.
+pub fn find_path_within(
+    world: &World,
+    start: TileCoord3,
+    goal: TileCoord3,
+    reach: i32,
+) -> Option<Path> {
+    find_path_to_nearest(world, start, &[goal], reach).map(|(_, path)| path)
+}
+
+// Shortest path to a tile within `reach` of any of `targets`. Returns the
+// target that was reached; ties go to the one listed first. The start tile
+// counts as reached even if it isn't passable, e.g. a drone that has just
+// been dug in.
+pub fn find_path_to_nearest(
+    world: &World,
+    start: TileCoord3,
+    targets: &[TileCoord3],
+    reach: i32,
+) -> Option<(TileCoord3, Path)> {
+    let order: HashMap<TileCoord3, usize> = targets
+        .iter()
+        .enumerate()
+        .rev()
+        .map(|(i, t)| (*t, i))
+        .collect();
+    let reached = |c: TileCoord3| -> Option<TileCoord3> {
+        let mut best: Option<(usize, TileCoord3)> = None;
+        for dx in -reach..=reach {
+            let span = reach - dx.abs();
+            for dy in -span..=span {
+                let t = TileCoord3::new(c.x + dx, c.y + dy, c.z);
+                if let Some(&i) = order.get(&t)
+                    && best.is_none_or(|(b, _)| i < b)
+                {
+                    best = Some((i, t));
+                }
+            }
+        }
+        best.map(|(_, t)| t)
+    };
+    let heuristic = |c: TileCoord3| -> u32 {
+        if targets.len() > MAX_HEURISTIC_TARGETS {
+            return 0;
+        }
+        targets
+            .iter()
+            .map(|t| {
+                let flat = (c.x - t.x).abs() + (c.y - t.y).abs();
+                ((flat - reach).max(0) + (c.z - t.z).abs()) as u32
+            })
+            .min()
+            .unwrap_or(0)
+    };
+
+    let key = |c: TileCoord3| (c.z, c.y, c.x);
+    let mut open = BinaryHeap::new();
+    let mut cost: HashMap<TileCoord3, u32> = HashMap::new();
+    let mut came_from: HashMap<TileCoord3, TileCoord3> = HashMap::new();
+    cost.insert(start, 0);
+    open.push(Reverse((heuristic(start), 0u32, key(start))));
+    while let Some(Reverse((_, g, (z, y, x)))) = open.pop() {
+        let c = TileCoord3::new(x, y, z);
+        if cost.get(&c).is_some_and(|best| g > *best) {
+            continue;
+        }
+        if let Some(target) = reached(c) {
+            let mut tiles = vec![c];
+            let mut at = c;
+            while let Some(prev) = came_from.get(&at) {
+                tiles.push(*prev);
+                at = *prev;
+            }
+            tiles.reverse();
+            return Some((target, Path { tiles }));
+        }
+        for n in neighbors(world, c) {
+            let next = g + 1;
+            if cost.get(&n).is_some_and(|best| next >= *best) {
+                continue;
+            }
+            cost.insert(n, next);
+            came_from.insert(n, c);
+            open.push(Reverse((next + heuristic(n), next, key(n))));
+        }
+    }
+    None
+}
+
+// Keeps paths around until the world's passability changes. A lookup also
+// hits when `start` lies anywhere on a cached path to the same goal, so a
+// drone walking a path finds the rest of it on the next tick.
+#[derive(Debug, Clone, Default)]
+pub struct PathCache {
+    revision: u64,
+    paths: HashMap<(TileCoord3, i32), Vec<Path>>,
+}
+
+impl PathCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.paths.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.paths.clear();
+    }
+
+    fn sync(&mut self, world: &World) {
+        if self.revision != world.revision() {
+            self.revision = world.revision();
+            self.paths.clear();
+        }
+    }
+
+    pub fn lookup(
+        &mut self,
+        world: &World,
+        start: TileCoord3,
+        goal: TileCoord3,
+        reach: i32,
+    ) -> Option<Path> {
+        self.sync(world);
+        self.peek(world, start, goal, reach)
+    }
+
+    // `lookup` without clearing out of date paths, so it can be shared
+    // between threads; misses if the world has changed since they were cached.
+    pub fn peek(
+        &self,
+        world: &World,
+        start: TileCoord3,
+        goal: TileCoord3,
+        reach: i32,
+    ) -> Option<Path> {
+        if self.revision != world.revision() {
+            return None;
+        }
+        self.paths
+            .get(&(goal, reach))?
+            .iter()
+            .find_map(|p| p.from_tile(start))
+    }
+
+    pub fn insert(&mut self, world: &World, goal: TileCoord3, reach: i32, path: Path) {
+        self.sync(world);
+        self.paths.entry((goal, reach)).or_default().push(path);
+    }
+
+    // Cached path if there is one, otherwise a fresh A* search.
+    pub fn find_within(
+        &mut self,
+        world: &World,
+        start: TileCoord3,
+        goal: TileCoord3,
+        reach: i32,
+    ) -> Option<Path> {
+        if let Some(path) = self.lookup(world, start, goal, reach) {
+            return Some(path);
+        }
+        let path = find_path_within(world, start, goal, reach)?;
+        self.insert(world, goal, reach, path.clone());
+        Some(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Stone world with a serpentine corridor carved through it: every other
+    // row is open, joined at alternating ends.
+    fn serpentine(width: i32, rows: i32) -> World {
+        let mut world = World::new(width, rows, 1, TileKind::Stone);
+        for y in (0..rows).step_by(2) {
+            for x in 0..width {
+                world.set_tile(TileCoord3::new(x, y, 0), TileKind::Air);
+            }
+            if y + 1 < rows {
+                let x = if (y / 2) % 2 == 0 { width - 1 } else { 0 };
+                world.set_tile(TileCoord3::new(x, y + 1, 0), TileKind::Floor);
+            }
+        }
+        world
+    }
+
+    #[test]
+    fn follows_corridor_and_avoids_stone() {
+        let world = serpentine(6, 5);
+        let start = TileCoord3::new(0, 0, 0);
+        let goal = TileCoord3::new(0, 4, 0);
+        let path = find_path(&world, start, goal).unwrap();
+        assert_eq!(path.start(), start);
+        assert_eq!(path.end(), goal);
+        // Two rows of 5 moves plus two connectors of 2 moves each
+        assert_eq!(path.len(), 14);
+        assert!(path.tiles.iter().all(|c| world.is_passable(*c)));
+        for pair in path.tiles.windows(2) {
+            assert_eq!(pair[0].manhattan(pair[1]), 1);
+        }
+
+        // Walled off entirely
+        let mut sealed = world.clone();
+        sealed.set_tile(TileCoord3::new(5, 1, 0), TileKind::Wall);
+        assert!(find_path(&sealed, start, goal).is_none());
+        // But a tile next to the wall can still be reached
+        let near = find_path_within(&sealed, start, TileCoord3::new(5, 1, 0), 1).unwrap();
+        assert_eq!(near.end(), TileCoord3::new(5, 0, 0));
+    }
+
+    #[test]
+    fn levels_connect_only_through_stairs() {
+        let mut world = World::new(4, 1, 2, TileKind::Air);
+        let start = TileCoord3::new(0, 0, 0);
+        let goal = TileCoord3::new(0, 0, 1);
+        assert!(find_path(&world, start, goal).is_none());
+        world.set_tile(TileCoord3::new(3, 0, 0), TileKind::Stairs);
+        world.set_tile(TileCoord3::new(3, 0, 1), TileKind::Stairs);
+        let path = find_path(&world, start, goal).unwrap();
+        assert_eq!(path.len(), 7);
+        assert!(path.tiles.contains(&TileCoord3::new(3, 0, 1)));
+    }
+
+    #[test]
+    fn cache_reuses_suffixes_until_world_changes() {
+        let mut world = serpentine(6, 3);
+        let mut cache = PathCache::new();
+        let goal = TileCoord3::new(5, 2, 0);
+        let path = cache
+            .find_within(&world, TileCoord3::new(0, 0, 0), goal, 0)
+            .unwrap();
+        // A drone two steps along gets the rest of the same path
+        let rest = cache.lookup(&world, path.tiles[2], goal, 0).unwrap();
+        assert_eq!(rest.tiles, path.tiles[2..].to_vec());
+        assert_eq!(cache.len(), 1);
+
+        world.mine_tile(TileCoord3::new(0, 1, 0));
+        assert!(cache.lookup(&world, path.tiles[2], goal, 0).is_none());
+        assert!(cache.is_empty());
+    }
+
+    // Steps from `start` to every tile it can reach, by breadth-first search.
+    fn bfs(world: &World, start: TileCoord3) -> HashMap<TileCoord3, usize> {
+        let mut dist = HashMap::from([(start, 0)]);
+        let mut queue = std::collections::VecDeque::from([start]);
+        while let Some(c) = queue.pop_front() {
+            for n in neighbors(world, c) {
+                if !dist.contains_key(&n) {
+                    dist.insert(n, dist[&c] + 1);
+                    queue.push_back(n);
+                }
+            }
+        }
+        dist
+    }
+
+    #[test]
+    fn paths_on_generated_worlds_are_shortest() {
+        let open: Vec<_> = (0..4)
+            .map(|seed| World::generate(24, 24, 1, seed, 0.05, 0.25))
+            .chain((0..4).map(|seed| World::from_seed_with_distribution(24, 24, 1, seed)))
+            .collect();
+        for world in &open {
+            let tiles: Vec<_> = (0..24)
+                .flat_map(|y| (0..24).map(move |x| TileCoord3::new(x, y, 0)))
+                .filter(|c| world.is_passable(*c))
+                .collect();
+            for start in tiles.iter().step_by(29) {
+                let dist = bfs(world, *start);
+                for goal in tiles.iter().step_by(13) {
+                    let Some(path) = find_path(world, *start, *goal) else {
+                        assert!(!dist.contains_key(goal), "missed {:?}", goal);
+                        continue;
+                    };
+                    assert_eq!((path.start(), path.end()), (*start, *goal));
+                    for step in path.tiles.windows(2) {
+                        assert!(neighbors(world, step[0]).contains(&step[1]));
+                    }
+                    assert_eq!(path.len(), dist[goal]);
+                    // What the cache hands a drone partway along is what a
+                    // fresh search from there would find
+                    let mid = path.tiles[path.tiles.len() / 2];
+                    assert_eq!(path.from_tile(mid), find_path(world, mid, *goal));
+                }
+            }
+        }
+    }
+}
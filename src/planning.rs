@@ -0,0 +1,203 @@
+use std::collections::{HashMap, HashSet};
+
+use rayon::prelude::*;
+
+use crate::chunk_graph::{CHUNK_SIZE, ChunkGraph};
+use crate::coords::TileCoord3;
+use crate::pathfinding::{Path, PathCache, find_path_to_nearest, neighbors};
+use crate::world::World;
+
+// Trips longer than this many tiles use the chunk graph.
+const LONG_TRIP: i32 = 2 * CHUNK_SIZE;
+
+// Fewest searches handed to a thread; fewer than this all run on the
+// calling thread.
+const SEARCHES_PER_THREAD: usize = 4;
+
+// A path search for a drone: from `start` to within `reach` of the nearest
+// of `targets`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct RouteQuery {
+    start: TileCoord3,
+    targets: Vec<TileCoord3>,
+    reach: i32,
+    // A long trip to a single target, through the chunk graph
+    long: bool,
+}
+
+impl RouteQuery {
+    pub(crate) fn nearest(start: TileCoord3, targets: Vec<TileCoord3>, reach: i32) -> Self {
+        Self {
+            start,
+            targets,
+            reach,
+            long: false,
+        }
+    }
+
+    // A trip to `goal`; long ones skip the full A* for the chunk graph.
+    pub(crate) fn travel(start: TileCoord3, goal: TileCoord3, reach: i32) -> Self {
+        Self {
+            start,
+            targets: vec![goal],
+            reach,
+            long: reach == 0 && start.manhattan(goal) > LONG_TRIP,
+        }
+    }
+
+    pub(crate) fn is_long(&self) -> bool {
+        self.long
+    }
+
+    pub(crate) fn reach(&self) -> i32 {
+        self.reach
+    }
+
+    // Whether the cache may answer this search. The cache isn't saved, so
+    // a cached path must be the one a fresh search would find. That holds
+    // for the rest of an A* path to one target, but from partway along a
+    // path to the nearest of several targets another may now be nearer,
+    // and a chunk graph route may take a different detour.
+    pub(crate) fn is_cacheable(&self) -> bool {
+        !self.long && self.targets.len() == 1
+    }
+
+    // A cached path from `start` and the target it leads to.
+    pub(crate) fn cached(&self, world: &World, cache: &PathCache) -> Option<(TileCoord3, Path)> {
+        if !self.is_cacheable() {
+            return None;
+        }
+        let target = self.targets[0];
+        let path = cache.peek(world, self.start, target, self.reach)?;
+        Some((target, path))
+    }
+
+    // A fresh search; `chunks` must be up to date for long trips.
+    pub(crate) fn search(&self, world: &World, chunks: &ChunkGraph) -> Option<(TileCoord3, Path)> {
+        if self.long {
+            let goal = self.targets[0];
+            return chunks
+                .find_path(world, self.start, goal)
+                .map(|path| (goal, path));
+        }
+        find_path_to_nearest(world, self.start, &self.targets, self.reach)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Route {
+    pub(crate) target: TileCoord3,
+    pub(crate) path: Path,
+    // Came from the path cache, so there is nothing to add to it
+    pub(crate) cached: bool,
+}
+
+// What was planned for a query, as of the world when drones take their turn.
+pub(crate) enum Planned<'a> {
+    Route(&'a Route),
+    Unreachable,
+    // Not planned, or the plan no longer holds
+    Unknown,
+}
+
+// Paths for the searches drones are expected to make this tick, all found
+// up front, in parallel, against the world as it was before any drone
+// moved. Drones then act one at a time, and a plan only stands while the
+// world hasn't changed under it: once it has, a planned path is kept if it
+// can still be walked, and anything else is searched for again.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RoutePlans {
+    revision: u64,
+    routes: HashMap<RouteQuery, Option<Route>>,
+}
+
+impl RoutePlans {
+    pub(crate) fn plan(
+        world: &World,
+        chunks: &ChunkGraph,
+        cache: &PathCache,
+        queries: Vec<RouteQuery>,
+    ) -> Self {
+        // Drones heading the same way share a search
+        let unique: HashSet<RouteQuery> = queries.into_iter().collect();
+        let routes = Vec::from_iter(unique)
+            .into_par_iter()
+            .with_min_len(SEARCHES_PER_THREAD)
+            .map(|query| {
+                let route = match query.cached(world, cache) {
+                    Some((target, path)) => Some(Route {
+                        target,
+                        path,
+                        cached: true,
+                    }),
+                    None => query.search(world, chunks).map(|(target, path)| Route {
+                        target,
+                        path,
+                        cached: false,
+                    }),
+                };
+                (query, route)
+            })
+            .collect();
+        Self {
+            revision: world.revision(),
+            routes,
+        }
+    }
+
+    // Whether the plans were made against `world` as it is now.
+    pub(crate) fn is_current(&self, world: &World) -> bool {
+        self.revision == world.revision()
+    }
+
+    pub(crate) fn get(&self, world: &World, query: &RouteQuery) -> Planned<'_> {
+        let current = self.is_current(world);
+        match self.routes.get(query) {
+            Some(Some(route)) if current || is_walkable(world, &route.path) => {
+                Planned::Route(route)
+            }
+            Some(None) if current => Planned::Unreachable,
+            _ => Planned::Unknown,
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.routes.clear();
+    }
+}
+
+// Every step of `path` can still be taken.
+fn is_walkable(world: &World, path: &Path) -> bool {
+    path.tiles
+        .windows(2)
+        .all(|step| neighbors(world, step[0]).contains(&step[1]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tile::TileKind;
+
+    #[test]
+    fn plans_hold_until_the_world_blocks_them() {
+        let mut world = World::new(8, 3, 1, TileKind::Air);
+        let chunks = ChunkGraph::new(&world);
+        let start = TileCoord3::new(0, 0, 0);
+        let query = RouteQuery::travel(start, TileCoord3::new(7, 0, 0), 0);
+        let elsewhere = RouteQuery::nearest(start, vec![TileCoord3::new(0, 2, 0)], 0);
+        let plans = RoutePlans::plan(&world, &chunks, &PathCache::new(), vec![query.clone()]);
+        assert!(matches!(plans.get(&world, &query), Planned::Route(r) if r.path.len() == 7));
+        assert!(matches!(plans.get(&world, &elsewhere), Planned::Unknown));
+
+        // A change off the path leaves the plan standing
+        world.set_tile(TileCoord3::new(3, 2, 0), TileKind::Stone);
+        assert!(!plans.is_current(&world));
+        let Planned::Route(route) = plans.get(&world, &query) else {
+            panic!("plan dropped");
+        };
+        // A wall across it doesn't
+        let blocked = route.path.tiles[3];
+        world.set_tile(blocked, TileKind::Stone);
+        assert!(matches!(plans.get(&world, &query), Planned::Unknown));
+    }
+}
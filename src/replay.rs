@@ -0,0 +1,177 @@
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::command::CommandRecord;
+use crate::engine::Engine;
+use crate::save::{SAVE_VERSION, SaveError, SaveFormat, SaveGame, decode, encode, write_file};
+
+// Ticks between state hashes while recording: often enough to narrow a
+// divergence down, rare enough not to show up in a profile.
+pub const DEFAULT_HASH_INTERVAL: u64 = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub tick: u64,
+    pub hash: u64,
+}
+
+#[derive(Debug, Error)]
+pub enum ReplayError {
+    #[error(transparent)]
+    Save(#[from] SaveError),
+    #[error("Replay diverged at tick {tick}: expected state {expected:016x}, got {actual:016x}")]
+    Diverged {
+        tick: u64,
+        expected: u64,
+        actual: u64,
+    },
+}
+
+// A recorded game: the state it started from, every command with its tick,
+// and state hashes taken along the way to check a re-run against.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub start: SaveGame,
+    pub commands: Vec<CommandRecord>,
+    pub hash_interval: u64,
+    // Taken after the tick, before that tick's commands
+    pub checkpoints: Vec<Checkpoint>,
+    // Where the recording stopped, after the last commands
+    pub end: Checkpoint,
+}
+
+impl Replay {
+    pub(crate) fn new(start: SaveGame, start_hash: u64, hash_interval: u64) -> Self {
+        let tick = start.clock.tick();
+        let first = Checkpoint {
+            tick,
+            hash: start_hash,
+        };
+        Self {
+            start,
+            commands: Vec::new(),
+            hash_interval: hash_interval.max(1),
+            checkpoints: vec![first],
+            end: first,
+        }
+    }
+
+    // Rebuilds the game from the start and checks every hash on the way.
+    // Returns the engine as it was when recording stopped.
+    pub fn run(&self) -> Result<Engine, ReplayError> {
+        let mut engine = Engine::from_save(self.start.clone());
+        let mut commands = self.commands.iter().peekable();
+        let mut checkpoints = self.checkpoints.iter().peekable();
+        loop {
+            let tick = engine.clock.tick();
+            while let Some(c) = checkpoints.next_if(|c| c.tick <= tick) {
+                verify(&engine, *c)?;
+            }
+            while let Some(record) = commands.next_if(|r| r.tick <= tick) {
+                // Commands that failed when recorded fail the same way here
+                let _ = engine.apply(record.command.clone());
+            }
+            if tick >= self.end.tick {
+                break;
+            }
+            engine.tick();
+        }
+        verify(&engine, self.end)?;
+        Ok(engine)
+    }
+
+    pub fn to_bytes(&self, format: SaveFormat) -> Result<Vec<u8>, SaveError> {
+        encode(self, format)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SaveError> {
+        let replay: Replay = decode(bytes)?;
+        if replay.start.version > SAVE_VERSION {
+            return Err(SaveError::UnsupportedVersion(replay.start.version));
+        }
+        Ok(replay)
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>, format: SaveFormat) -> Result<(), SaveError> {
+        write_file(path.as_ref(), &self.to_bytes(format)?)
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, SaveError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+}
+
+fn verify(engine: &Engine, expected: Checkpoint) -> Result<(), ReplayError> {
+    let actual = engine.state_hash();
+    if actual == expected.hash {
+        Ok(())
+    } else {
+        Err(ReplayError::Diverged {
+            tick: expected.tick,
+            expected: expected.hash,
+            actual,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::Command;
+    use crate::coords::{TileBox3, TileCoord3};
+    use crate::drones::Drone;
+    use crate::tasks::Task;
+    use crate::tile::TileKind;
+    use crate::world::World;
+
+    fn quarry(x: i32) -> Task {
+        let c = TileCoord3::new(x, 1, 0);
+        Task::MineBox(TileBox3::new(c, c))
+    }
+
+    #[test]
+    fn replay_reproduces_the_recorded_game() {
+        let mut world = World::new(10, 2, 1, TileKind::Stone);
+        for x in 0..10 {
+            world.set_tile(TileCoord3::new(x, 0, 0), TileKind::Air);
+        }
+        world.set_core(TileCoord3::new(0, 0, 0));
+        let mut worn = Drone::new(1);
+        worn.durability.current = 30;
+        let mut engine = Engine::new(world, vec![worn, Drone::new(2)]);
+        engine.reseed(3);
+        let (first, _) = engine.queue_task(quarry(9));
+        engine.cancel_task(first);
+        engine.start_recording(5);
+
+        for round in 0..6 {
+            let task = quarry(round + 2);
+            engine
+                .apply(Command::QueueTask { task, drone: None })
+                .unwrap();
+            for _ in 0..round + 3 {
+                engine.tick();
+            }
+        }
+        assert!(engine.apply(Command::CancelTask(first)).is_err());
+        let replay = engine.stop_recording().unwrap();
+        // The failed cancel changed nothing and isn't kept
+        assert_eq!(replay.commands.len(), 6);
+        assert!(replay.checkpoints.len() > 5);
+
+        let bytes = replay.to_bytes(SaveFormat::Binary).unwrap();
+        let rerun = Replay::from_bytes(&bytes).unwrap().run().unwrap();
+        assert_eq!(rerun.state_hash(), engine.state_hash());
+
+        // Drop one command and the run goes its own way
+        let mut tampered = replay.clone();
+        tampered.commands.remove(2);
+        assert!(matches!(
+            tampered.run(),
+            Err(ReplayError::Diverged { tick, .. }) if tick > replay.commands[2].tick
+        ));
+    }
+}
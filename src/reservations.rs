@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::coords::TileCoord3;
+use crate::tasks::TaskId;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReservationConflict {
+    pub tile: TileCoord3,
+    pub held_by: TaskId,
+}
+
+// Claim table: a tile can be held by at most one task at a time.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Reservations {
+    #[serde(with = "crate::coords::coord_map")]
+    claims: HashMap<TileCoord3, TaskId>,
+}
+
+impl Reservations {
+    pub fn new() -> Self {
+        Self {
+            claims: HashMap::new(),
+        }
+    }
+
+    pub fn claimant(&self, c: TileCoord3) -> Option<TaskId> {
+        self.claims.get(&c).copied()
+    }
+
+    pub fn is_reserved(&self, c: TileCoord3) -> bool {
+        self.claims.contains_key(&c)
+    }
+
+    pub fn len(&self) -> usize {
+        self.claims.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.claims.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (TileCoord3, TaskId)> + '_ {
+        self.claims.iter().map(|(c, id)| (*c, *id))
+    }
+
+    // Tiles in `tiles` already held by a task other than `task`.
+    pub fn conflicts(&self, task: TaskId, tiles: &[TileCoord3]) -> Vec<ReservationConflict> {
+        tiles
+            .iter()
+            .filter_map(|c| match self.claims.get(c) {
+                Some(held_by) if *held_by != task => Some(ReservationConflict {
+                    tile: *c,
+                    held_by: *held_by,
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // All-or-nothing: either every tile is claimed for `task` or none is.
+    pub fn try_claim(
+        &mut self,
+        task: TaskId,
+        tiles: &[TileCoord3],
+    ) -> Result<(), Vec<ReservationConflict>> {
+        let conflicts = self.conflicts(task, tiles);
+        if !conflicts.is_empty() {
+            return Err(conflicts);
+        }
+        for c in tiles {
+            self.claims.insert(*c, task);
+        }
+        Ok(())
+    }
+
+    pub fn release(&mut self, task: TaskId) {
+        self.claims.retain(|_, held_by| *held_by != task);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn claim_conflict_and_release() {
+        let mut r = Reservations::new();
+        let a = TileCoord3::new(0, 0, 0);
+        let b = TileCoord3::new(1, 0, 0);
+        assert!(r.try_claim(0, &[a, b]).is_ok());
+        assert_eq!(r.claimant(a), Some(0));
+
+        let err = r.try_claim(1, &[b, TileCoord3::new(2, 0, 0)]).unwrap_err();
+        assert_eq!(
+            err,
+            vec![ReservationConflict {
+                tile: b,
+                held_by: 0
+            }]
+        );
+        // Failed claims leave nothing behind
+        assert_eq!(r.len(), 2);
+
+        r.release(0);
+        assert!(r.is_empty());
+        assert!(r.try_claim(1, &[b]).is_ok());
+    }
+
+    #[test]
+    fn reclaiming_own_tiles_is_not_a_conflict() {
+        let mut r = Reservations::new();
+        let a = TileCoord3::new(0, 0, 0);
+        assert!(r.try_claim(3, &[a]).is_ok());
+        assert!(r.try_claim(3, &[a]).is_ok());
+        assert!(r.conflicts(3, &[a]).is_empty());
+    }
+}
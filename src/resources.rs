@@ -1,4 +1,6 @@
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub struct Resources {
     pub stone: u32,
     pub iron: u32,
@@ -16,6 +18,34 @@ impl Resources {
     pub fn add_iron(&mut self, amount: u32) {
         self.iron = self.iron.saturating_add(amount);
     }
+
+    pub fn add(&mut self, other: &Resources) {
+        self.add_stone(other.stone);
+        self.add_iron(other.iron);
+    }
+
+    // Item units of every kind together.
+    pub fn total(&self) -> u32 {
+        self.stone.saturating_add(self.iron)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stone == 0 && self.iron == 0
+    }
+
+    pub fn can_afford(&self, cost: &Resources) -> bool {
+        self.stone >= cost.stone && self.iron >= cost.iron
+    }
+
+    // Deducts `cost` only when all of it is available.
+    pub fn try_spend(&mut self, cost: &Resources) -> bool {
+        if !self.can_afford(cost) {
+            return false;
+        }
+        self.stone -= cost.stone;
+        self.iron -= cost.iron;
+        true
+    }
 }
 
 #[cfg(test)]
@@ -30,4 +60,13 @@ mod tests {
         assert_eq!(r.stone, 3);
         assert_eq!(r.iron, 2);
     }
+
+    #[test]
+    fn spend_is_all_or_nothing() {
+        let mut r = Resources { stone: 3, iron: 0 };
+        assert!(!r.try_spend(&Resources { stone: 1, iron: 1 }));
+        assert_eq!(r, Resources { stone: 3, iron: 0 });
+        assert!(r.try_spend(&Resources { stone: 2, iron: 0 }));
+        assert_eq!(r.stone, 1);
+    }
 }
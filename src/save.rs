@@ -0,0 +1,207 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use rand_chacha::ChaCha8Rng;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::archetypes::Archetype;
+use crate::blackboard::Blackboard;
+use crate::clock::SimClock;
+use crate::drones::Drone;
+use crate::reservations::Reservations;
+use crate::scenario::Wave;
+use crate::script::Script;
+use crate::session::Session;
+use crate::tasks::{TaskId, TaskManager};
+use crate::world::World;
+
+// Bump when the saved layout changes; older files are then migrated or
+// turned away in `SaveGame::from_bytes`.
+pub const SAVE_VERSION: u32 = 1;
+
+// Binary saves start with this so they can't be mistaken for JSON.
+const BINARY_MAGIC: &[u8; 4] = b"DFSV";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaveFormat {
+    // CBOR, for everyday saves
+    Binary,
+    // Pretty JSON, for reading and diffing by hand
+    Json,
+}
+
+#[derive(Debug, Error)]
+pub enum SaveError {
+    #[error("I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Couldn't encode save: {0}")]
+    Encode(String),
+    #[error("Couldn't read save: {0}")]
+    Decode(String),
+    #[error("Save version {0} is newer than this build supports")]
+    UnsupportedVersion(u32),
+}
+
+// Everything needed to pick a game up where it left off. Caches such as
+// paths and the chunk graph are rebuilt from the world on load.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SaveGame {
+    pub version: u32,
+    pub(crate) world: World,
+    pub(crate) drones: Vec<Drone>,
+    pub(crate) tasks: TaskManager,
+    pub(crate) reservations: Reservations,
+    pub(crate) archetypes: Vec<Archetype>,
+    pub(crate) fabricating: Option<(TaskId, u32)>,
+    pub(crate) rng: ChaCha8Rng,
+    pub(crate) blackboard: Blackboard,
+    pub(crate) scripts: Vec<Script>,
+    pub(crate) clock: SimClock,
+    #[serde(default)]
+    pub(crate) waves: Vec<Wave>,
+    #[serde(default)]
+    pub(crate) session: Session,
+}
+
+// Read ahead of the full save so a newer layout fails with a clear error
+// rather than a field mismatch.
+#[derive(Deserialize)]
+struct Header {
+    version: u32,
+}
+
+impl SaveGame {
+    pub fn to_bytes(&self, format: SaveFormat) -> Result<Vec<u8>, SaveError> {
+        encode(self, format)
+    }
+
+    // Takes either encoding.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SaveError> {
+        let header: Header = decode(bytes)?;
+        if header.version > SAVE_VERSION {
+            return Err(SaveError::UnsupportedVersion(header.version));
+        }
+        let save: SaveGame = decode(bytes)?;
+        if !save.world.is_well_formed() {
+            return Err(SaveError::Decode(
+                "tile grid doesn't match the map size".into(),
+            ));
+        }
+        Ok(save)
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>, format: SaveFormat) -> Result<(), SaveError> {
+        write_file(path.as_ref(), &self.to_bytes(format)?)
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, SaveError> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+}
+
+pub(crate) fn encode<T: Serialize>(value: &T, format: SaveFormat) -> Result<Vec<u8>, SaveError> {
+    match format {
+        SaveFormat::Binary => {
+            let mut out = BINARY_MAGIC.to_vec();
+            ciborium::into_writer(value, &mut out).map_err(|e| SaveError::Encode(e.to_string()))?;
+            Ok(out)
+        }
+        SaveFormat::Json => {
+            serde_json::to_vec_pretty(value).map_err(|e| SaveError::Encode(e.to_string()))
+        }
+    }
+}
+
+// Takes either encoding.
+pub(crate) fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, SaveError> {
+    match bytes.strip_prefix(BINARY_MAGIC) {
+        Some(body) => ciborium::from_reader(body).map_err(|e| SaveError::Decode(e.to_string())),
+        None => serde_json::from_slice(bytes).map_err(|e| SaveError::Decode(e.to_string())),
+    }
+}
+
+// Writes to a temporary file first, so a crash mid-save leaves the
+// previous file intact.
+pub(crate) fn write_file(path: &Path, bytes: &[u8]) -> Result<(), SaveError> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, bytes)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coords::{TileBox3, TileCoord3};
+    use crate::engine::Engine;
+    use crate::tasks::Task;
+    use crate::tile::TileKind;
+
+    fn game() -> Engine {
+        let mut world = World::new(8, 2, 1, TileKind::Stone);
+        for x in 0..8 {
+            world.set_tile(TileCoord3::new(x, 0, 0), TileKind::Air);
+        }
+        world.set_core(TileCoord3::new(0, 0, 0));
+        let mut drone = Drone::new(1);
+        // Worn enough that breakdowns depend on the RNG
+        drone.durability.current = 20;
+        let mut engine = Engine::new(world, vec![drone]);
+        engine.reseed(9);
+        let row = TileBox3::new(TileCoord3::new(0, 1, 0), TileCoord3::new(7, 1, 0));
+        engine.queue_task(Task::MineBox(row));
+        for _ in 0..5 {
+            engine.tick();
+        }
+        engine
+    }
+
+    fn state(engine: &Engine) -> Vec<u8> {
+        engine.to_save().to_bytes(SaveFormat::Json).unwrap()
+    }
+
+    #[test]
+    fn loaded_game_carries_on_exactly_like_the_original() {
+        let mut original = game();
+        let binary = original.to_save().to_bytes(SaveFormat::Binary).unwrap();
+        let json = original.to_save().to_bytes(SaveFormat::Json).unwrap();
+        assert!(binary.len() < json.len());
+
+        let mut from_binary = Engine::from_save(SaveGame::from_bytes(&binary).unwrap());
+        let mut from_json = Engine::from_save(SaveGame::from_bytes(&json).unwrap());
+        assert_eq!(from_binary.clock.tick(), 5);
+        for _ in 0..30 {
+            original.tick();
+            from_binary.tick();
+            from_json.tick();
+        }
+        assert_eq!(state(&from_binary), state(&original));
+        assert_eq!(state(&from_json), state(&original));
+    }
+
+    #[test]
+    fn rejects_newer_and_damaged_saves() {
+        let engine = game();
+        let path = std::env::temp_dir().join(format!("droneforge-{}.sav", std::process::id()));
+        engine.save(&path, SaveFormat::Binary).unwrap();
+        assert_eq!(state(&Engine::load(&path).unwrap()), state(&engine));
+        std::fs::remove_file(&path).unwrap();
+
+        let mut json: serde_json::Value =
+            serde_json::from_slice(&engine.to_save().to_bytes(SaveFormat::Json).unwrap()).unwrap();
+        json["version"] = (SAVE_VERSION + 1).into();
+        let newer = serde_json::to_vec(&json).unwrap();
+        assert!(matches!(
+            SaveGame::from_bytes(&newer),
+            Err(SaveError::UnsupportedVersion(v)) if v == SAVE_VERSION + 1
+        ));
+        let binary = engine.to_save().to_bytes(SaveFormat::Binary).unwrap();
+        assert!(matches!(
+            SaveGame::from_bytes(&binary[..binary.len() / 2]),
+            Err(SaveError::Decode(_))
+        ));
+    }
+}
@@ -0,0 +1,418 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::archetypes::{Archetype, builtin_archetypes, find_archetype};
+use crate::coords::{TileBox3, TileCoord3};
+use crate::drones::Drone;
+use crate::dsl_ast::{CompileError, Program};
+use crate::engine::Engine;
+use crate::resources::Resources;
+use crate::session::{Session, VictoryCondition};
+use crate::tile::TileKind;
+use crate::world::World;
+
+#[derive(Debug, Error)]
+pub enum ScenarioError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Couldn't parse scenario: {0}")]
+    Parse(String),
+    #[error("Bad layout: {0}")]
+    BadLayout(String),
+    #[error("{0:?} is outside the map")]
+    OutOfBounds(TileCoord3),
+    #[error("Unknown drone type: {0}")]
+    UnknownDroneType(String),
+    #[error("Drone type {0} is already defined")]
+    DuplicateDroneType(String),
+    #[error("Program {0}: {1}")]
+    Program(usize, CompileError),
+}
+
+// A map and its starting conditions, authored as JSON, e.g.
+// scenarios/default.json.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Scenario {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub map: MapSpec,
+    // Painted over the map in order, e.g. to clear a landing zone
+    #[serde(default)]
+    pub areas: Vec<AreaFill>,
+    #[serde(default)]
+    pub resources: Resources,
+    // Defaults to the middle of the bottom level
+    #[serde(default)]
+    pub core: Option<TileCoord3>,
+    #[serde(default)]
+    pub stockpiles: Vec<TileBox3>,
+    // Drone types on top of the built-in ones, for the drones below and for
+    // the core to fabricate
+    #[serde(default)]
+    pub archetypes: Vec<Archetype>,
+    // Numbered from 1 in the order listed
+    #[serde(default)]
+    pub drones: Vec<DroneSpec>,
+    // Queued before the first tick
+    #[serde(default)]
+    pub programs: Vec<Program>,
+    #[serde(default)]
+    pub waves: Vec<Wave>,
+    // Any one of these wins; without any the game only ends in defeat
+    #[serde(default)]
+    pub victory: Vec<VictoryCondition>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum MapSpec {
+    Generated {
+        width: i32,
+        height: i32,
+        #[serde(default = "one_level")]
+        levels: i32,
+        seed: u64,
+        #[serde(default = "default_iron_share")]
+        iron_share: f32,
+        #[serde(default = "default_stone_share")]
+        stone_share: f32,
+    },
+    // One list of rows per level, row 0 being y = 0. Every row on every
+    // level must have the same width.
+    Layout {
+        levels: Vec<Vec<String>>,
+        // Extra or replacement symbols on top of `default_legend`
+        #[serde(default)]
+        legend: BTreeMap<char, TileKind>,
+    },
+}
+
+fn one_level() -> i32 {
+    1
+}
+
+fn default_iron_share() -> f32 {
+    0.10
+}
+
+fn default_stone_share() -> f32 {
+    0.45
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AreaFill {
+    pub area: TileBox3,
+    pub tile: TileKind,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DroneSpec {
+    // Archetype name; a plain drone when left out
+    #[serde(default, rename = "type")]
+    pub archetype: Option<String>,
+    pub at: TileCoord3,
+}
+
+// An attack due at `tick`, shown as a countdown in the HUD.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Wave {
+    pub tick: u64,
+    pub enemies: u32,
+}
+
+pub fn default_legend() -> BTreeMap<char, TileKind> {
+    BTreeMap::from([
+        ('.', TileKind::Air),
+        ('#', TileKind::Stone),
+        ('*', TileKind::Iron),
+        ('W', TileKind::Wall),
+        ('_', TileKind::Floor),
+        ('H', TileKind::Stairs),
+        ('C', TileKind::Charger),
+    ])
+}
+
+impl Scenario {
+    pub fn from_json(text: &str) -> Result<Self, ScenarioError> {
+        serde_json::from_str(text).map_err(|e| ScenarioError::Parse(e.to_string()))
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ScenarioError> {
+        Self::from_json(&fs::read_to_string(path)?)
+    }
+
+    pub fn build_world(&self) -> Result<World, ScenarioError> {
+        let mut world = match &self.map {
+            MapSpec::Generated {
+                width,
+                height,
+                levels,
+                seed,
+                iron_share,
+                stone_share,
+            } => {
+                if *width <= 0 || *height <= 0 || *levels <= 0 {
+                    return Err(ScenarioError::BadLayout("empty map".into()));
+                }
+                World::generate(*width, *height, *levels, *seed, *iron_share, *stone_share)
+            }
+            MapSpec::Layout { levels, legend } => layout_world(levels, legend)?,
+        };
+        let check = |c: TileCoord3| match world.get_tile(c) {
+            Some(_) => Ok(c),
+            None => Err(ScenarioError::OutOfBounds(c)),
+        };
+        for fill in &self.areas {
+            check(fill.area.min)?;
+            check(fill.area.max)?;
+        }
+        for zone in &self.stockpiles {
+            check(zone.min)?;
+            check(zone.max)?;
+        }
+        let core = match self.core {
+            Some(c) => check(c)?,
+            None => TileCoord3::new(world.width() / 2, world.height() / 2, 0),
+        };
+        for fill in &self.areas {
+            for c in fill.area.iter_tiles() {
+                world.set_tile(c, fill.tile);
+            }
+        }
+        for zone in &self.stockpiles {
+            world.add_stockpile(*zone);
+        }
+        world.set_core(core);
+        world.resources = self.resources;
+        Ok(world)
+    }
+
+    // A ready-to-run engine: map, drones and queued programs.
+    pub fn build(&self) -> Result<Engine, ScenarioError> {
+        let world = self.build_world()?;
+        let mut archetypes = builtin_archetypes();
+        for a in &self.archetypes {
+            if find_archetype(&archetypes, &a.name).is_some() {
+                return Err(ScenarioError::DuplicateDroneType(a.name.clone()));
+            }
+            archetypes.push(a.clone());
+        }
+        let mut drones = Vec::new();
+        for (i, spec) in self.drones.iter().enumerate() {
+            if world.get_tile(spec.at).is_none() {
+                return Err(ScenarioError::OutOfBounds(spec.at));
+            }
+            let id = i as u32 + 1;
+            let drone = match &spec.archetype {
+                Some(name) => {
+                    let archetype = find_archetype(&archetypes, name)
+                        .ok_or_else(|| ScenarioError::UnknownDroneType(name.clone()))?;
+                    Drone::of_type(id, archetype)
+                }
+                None => Drone::new(id),
+            };
+            drones.push(drone.at(spec.at));
+        }
+        // Drones go in up front so they don't show up as freshly fabricated
+        let mut engine = Engine::new(world, drones);
+        engine.archetypes = archetypes;
+        for (i, program) in self.programs.iter().enumerate() {
+            engine
+                .queue_program(program)
+                .map_err(|e| ScenarioError::Program(i, e))?;
+        }
+        engine.waves = self.waves.clone();
+        engine.session = Session::new(self.victory.clone());
+        Ok(engine)
+    }
+
+    // Like `build`, with a generated map and the engine's breakdowns both
+    // seeded from `seed`, e.g. for a fresh game on the same scenario.
+    pub fn build_with_seed(&self, seed: u64) -> Result<Engine, ScenarioError> {
+        let mut scenario = self.clone();
+        if let MapSpec::Generated { seed: map_seed, .. } = &mut scenario.map {
+            *map_seed = seed;
+        }
+        let mut engine = scenario.build()?;
+        engine.reseed(seed);
+        Ok(engine)
+    }
+}
+
+fn layout_world(
+    levels: &[Vec<String>],
+    extra: &BTreeMap<char, TileKind>,
+) -> Result<World, ScenarioError> {
+    let mut legend = default_legend();
+    legend.extend(extra);
+    let height = levels.first().map_or(0, Vec::len);
+    let width = levels
+        .first()
+        .and_then(|rows| rows.first())
+        .map_or(0, |row| row.chars().count());
+    if width == 0 || height == 0 {
+        return Err(ScenarioError::BadLayout("empty map".into()));
+    }
+    let mut world = World::new(
+        width as i32,
+        height as i32,
+        levels.len() as i32,
+        TileKind::Air,
+    );
+    for (z, rows) in levels.iter().enumerate() {
+        if rows.len() != height {
+            return Err(ScenarioError::BadLayout(format!(
+                "level {} has {} rows, expected {}",
+                z,
+                rows.len(),
+                height
+            )));
+        }
+        for (y, row) in rows.iter().enumerate() {
+            if row.chars().count() != width {
+                return Err(ScenarioError::BadLayout(format!(
+                    "row {} on level {} isn't {} tiles wide",
+                    y, z, width
+                )));
+            }
+            for (x, symbol) in row.chars().enumerate() {
+                let kind = legend.get(&symbol).copied().ok_or_else(|| {
+                    ScenarioError::BadLayout(format!("unknown symbol '{}'", symbol))
+                })?;
+                world.set_tile(TileCoord3::new(x as i32, y as i32, z as i32), kind);
+            }
+        }
+    }
+    Ok(world)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::SessionState;
+    use crate::tasks::{Task, TaskState};
+    use serde_json::json;
+
+    #[test]
+    fn layout_scenario_builds_engine() {
+        let scenario = Scenario::from_json(
+            &json!({
+                "name": "Tutorial: first shaft",
+                "map": {
+                    "kind": "Layout",
+                    "levels": [["#####", "..*..", "__C__"]],
+                    "legend": { "~": "Wall" }
+                },
+                "resources": { "stone": 5, "iron": 1 },
+                "core": { "x": 2, "y": 2, "z": 0 },
+                "drones": [
+                    { "type": "miner", "at": { "x": 0, "y": 1, "z": 0 } },
+                    { "at": { "x": 4, "y": 1, "z": 0 } }
+                ],
+                "programs": [{
+                    "version": 1, "node": "Program", "drone": 1,
+                    "statements": [{
+                        "node": "ExprStmt",
+                        "expr": {
+                            "node": "Call", "func": "mine_box",
+                            "args": [{
+                                "node": "TileBoxFromCoords",
+                                "min": { "node": "TileCoord", "x": 2, "y": 1 },
+                                "max": { "node": "TileCoord", "x": 2, "y": 1 }
+                            }]
+                        }
+                    }]
+                }],
+                "waves": [{ "tick": 600, "enemies": 3 }],
+                "victory": [{ "kind": "SurviveWaves", "waves": 1 }]
+            })
+            .to_string(),
+        )
+        .unwrap();
+        let engine = scenario.build().unwrap();
+        let world = &engine.world;
+        assert_eq!((world.width(), world.height(), world.levels()), (5, 3, 1));
+        assert_eq!(
+            world.get_tile(TileCoord3::new(2, 1, 0)),
+            Some(TileKind::Iron)
+        );
+        assert_eq!(
+            world.get_tile(TileCoord3::new(2, 2, 0)),
+            Some(TileKind::Charger)
+        );
+        assert_eq!(world.core(), Some(TileCoord3::new(2, 2, 0)));
+        assert_eq!(world.resources, Resources { stone: 5, iron: 1 });
+        assert_eq!(engine.drones[0].type_name(), Some("Miner"));
+        assert_eq!(engine.drones[1].id, 2);
+        assert!(matches!(
+            engine.tasks.tasks[0],
+            (Task::MineBox(_), TaskState::Pending)
+        ));
+        assert_eq!(engine.tasks.assigned_drone(0), Some(1));
+        assert_eq!(
+            engine.waves,
+            vec![Wave {
+                tick: 600,
+                enemies: 3
+            }]
+        );
+        assert_eq!(
+            engine.session.victory,
+            [VictoryCondition::SurviveWaves { waves: 1 }]
+        );
+        assert_eq!(engine.session_state(), SessionState::Setup);
+
+        let mut broken = scenario.clone();
+        broken.drones[0].archetype = Some("tank".into());
+        assert!(matches!(
+            broken.build(),
+            Err(ScenarioError::UnknownDroneType(_))
+        ));
+    }
+
+    #[test]
+    fn scenario_drone_types_join_the_builtin_ones() {
+        let digger = json!({
+            "name": "Digger", "speed": 1, "mining_power": 8, "cargo_capacity": 30,
+            "battery_capacity": 150, "accepts": ["Mine", "Move"],
+            "cost": { "stone": 10, "iron": 6 }, "build_time": 30
+        });
+        let scenario = |types: Vec<serde_json::Value>| {
+            Scenario::from_json(
+                &json!({
+                    "name": "Custom drones",
+                    "map": { "kind": "Layout", "levels": [["...", "..."]] },
+                    "archetypes": types,
+                    "drones": [{ "type": "digger", "at": { "x": 0, "y": 0, "z": 0 } }]
+                })
+                .to_string(),
+            )
+            .unwrap()
+        };
+        let mut engine = scenario(vec![digger.clone()]).build().unwrap();
+        assert_eq!(engine.drones[0].type_name(), Some("Digger"));
+        assert_eq!(engine.drones[0].mining_power, 8);
+        assert!(engine.fabricate("Digger").is_ok());
+        assert!(engine.fabricate("scout").is_ok());
+
+        let mut miner = digger;
+        miner["name"] = json!("miner");
+        assert!(matches!(
+            scenario(vec![miner]).build(),
+            Err(ScenarioError::DuplicateDroneType(_))
+        ));
+    }
+
+    #[test]
+    fn bundled_scenario_loads() {
+        let text = include_str!("../scenarios/default.json");
+        let engine = Scenario::from_json(text).unwrap().build().unwrap();
+        assert_eq!(engine.drones.len(), 4);
+        assert_eq!(engine.next_wave().map(|(n, _)| n), Some(1));
+    }
+}
@@ -0,0 +1,301 @@
+use serde::{Deserialize, Serialize};
+
+use crate::blackboard::{Blackboard, Value};
+use crate::dsl_ast::{CompileError, Expr, Program, Scope, Statement, check_let_type, compile_call};
+use crate::tasks::{Task, TaskId, TaskManager, TaskState};
+
+pub type ScriptId = usize;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScriptStatus {
+    Running,
+    // Suspended until the next tick, with what it is waiting on
+    Waiting(String),
+    Done,
+    Failed(String),
+}
+
+// Outcome of one statement.
+enum Exec {
+    Next,
+    Wait(String),
+    Queue(Task),
+}
+
+// A program run one statement at a time across ticks, so it can hand work
+// to the task queue and wait on other drones without blocking the engine.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Script {
+    pub program: Program,
+    pub status: ScriptStatus,
+    pc: usize,
+    scope: Scope,
+    // Task queued by the last call; the script resumes once it is done
+    task: Option<TaskId>,
+}
+
+impl Script {
+    pub fn new(program: Program) -> Self {
+        Self {
+            program,
+            status: ScriptStatus::Running,
+            pc: 0,
+            scope: Scope::default(),
+            task: None,
+        }
+    }
+
+    pub fn drone(&self) -> Option<u32> {
+        self.program.drone
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self.status, ScriptStatus::Done | ScriptStatus::Failed(_))
+    }
+
+    pub fn current_task(&self) -> Option<TaskId> {
+        self.task
+    }
+
+    // Called once the engine has queued the task `step` returned.
+    pub fn wait_on(&mut self, id: TaskId) {
+        self.task = Some(id);
+    }
+
+    pub fn fail(&mut self, reason: impl Into<String>) {
+        self.status = ScriptStatus::Failed(reason.into());
+    }
+
+    // Runs statements until one can't finish this tick. Returns a task for
+    // the engine to queue; the caller then hands its id to `wait_on` and
+    // steps again.
+    pub fn step(&mut self, tasks: &TaskManager, board: &mut Blackboard) -> Option<Task> {
+        loop {
+            if self.is_finished() {
+                return None;
+            }
+            if let Some(id) = self.task {
+                match tasks.state(id) {
+                    Some(TaskState::Done) => self.task = None,
+                    Some(TaskState::Cancelled) | None => {
+                        self.fail(format!("Task #{} was cancelled", id));
+                        return None;
+                    }
+                    Some(_) => {
+                        self.status = ScriptStatus::Waiting(format!("Waiting for task #{}", id));
+                        return None;
+                    }
+                }
+            }
+            let Some(stmt) = self.program.statements.get(self.pc).cloned() else {
+                self.status = ScriptStatus::Done;
+                return None;
+            };
+            match self.exec(&stmt, board) {
+                Ok(Exec::Next) => self.pc += 1,
+                Ok(Exec::Wait(reason)) => {
+                    self.status = ScriptStatus::Waiting(reason);
+                    return None;
+                }
+                Ok(Exec::Queue(task)) => {
+                    self.pc += 1;
+                    self.status = ScriptStatus::Running;
+                    return Some(task);
+                }
+                Err(e) => {
+                    self.fail(e.to_string());
+                    return None;
+                }
+            }
+        }
+    }
+
+    fn exec(&mut self, stmt: &Statement, board: &mut Blackboard) -> Result<Exec, CompileError> {
+        match stmt {
+            Statement::Let { name, ty, value } => {
+                check_let_type(ty)?;
+                let bound = match value {
+                    Expr::Call { func, args } if func == "recv" => match self.recv(args, board)? {
+                        Some(v) => value_to_expr(v),
+                        None => return Ok(Exec::Wait("Waiting for a message".into())),
+                    },
+                    Expr::Call { func, .. } => {
+                        return Err(CompileError::UnsupportedNode(format!(
+                            "Can't bind the result of {}",
+                            func
+                        )));
+                    }
+                    other => other.clone(),
+                };
+                self.scope.vars.insert(name.clone(), bound);
+                Ok(Exec::Next)
+            }
+            Statement::ExprStmt {
+                expr: Expr::Call { func, args },
+            } => self.call(func, args, board),
+            Statement::ExprStmt { .. } => Err(CompileError::UnsupportedNode(
+                "Only calls supported as statements".into(),
+            )),
+            Statement::ForIn { .. } => Err(CompileError::UnsupportedNode(
+                "ForIn not supported in M1".into(),
+            )),
+        }
+    }
+
+    fn call(
+        &mut self,
+        func: &str,
+        args: &[Expr],
+        board: &mut Blackboard,
+    ) -> Result<Exec, CompileError> {
+        match (func, args) {
+            ("send", [to, value]) => {
+                let to = match self.value(to)? {
+                    Value::Int(n) => u32::try_from(n).map_err(|_| CompileError::InvalidArg)?,
+                    _ => return Err(CompileError::InvalidArg),
+                };
+                let value = self.value(value)?;
+                board.send(to, self.program.drone, value);
+                Ok(Exec::Next)
+            }
+            ("recv", _) => Ok(match self.recv(args, board)? {
+                Some(_) => Exec::Next,
+                None => Exec::Wait("Waiting for a message".into()),
+            }),
+            ("set", [key, value]) => {
+                let key = self.text(key)?;
+                let value = self.value(value)?;
+                board.set(key, value);
+                Ok(Exec::Next)
+            }
+            ("set_flag", [name]) => {
+                board.set_flag(self.text(name)?);
+                Ok(Exec::Next)
+            }
+            ("wait_for", [name]) => {
+                let name = self.text(name)?;
+                Ok(if board.is_set(&name) {
+                    Exec::Next
+                } else {
+                    Exec::Wait(format!("Waiting for {}", name))
+                })
+            }
+            ("send" | "set" | "set_flag" | "wait_for", _) => Err(CompileError::InvalidArg),
+            _ => Ok(Exec::Queue(compile_call(func, args, &self.scope)?)),
+        }
+    }
+
+    fn recv(&self, args: &[Expr], board: &mut Blackboard) -> Result<Option<Value>, CompileError> {
+        if !args.is_empty() {
+            return Err(CompileError::InvalidArg);
+        }
+        let drone = self.program.drone.ok_or_else(|| {
+            CompileError::UnsupportedNode("recv needs a program bound to a drone".into())
+        })?;
+        Ok(board.recv(drone).map(|m| m.value))
+    }
+
+    fn value(&self, e: &Expr) -> Result<Value, CompileError> {
+        match e {
+            Expr::IntLiteral { value } => Ok(Value::Int(*value)),
+            Expr::StringLiteral { value } => Ok(Value::Text(value.clone())),
+            Expr::VarRef { name } => {
+                let bound = self
+                    .scope
+                    .vars
+                    .get(name)
+                    .ok_or_else(|| CompileError::UnknownVar(name.clone()))?;
+                self.value(bound)
+            }
+            _ => Err(CompileError::InvalidArg),
+        }
+    }
+
+    fn text(&self, e: &Expr) -> Result<String, CompileError> {
+        match self.value(e)? {
+            Value::Text(s) => Ok(s),
+            _ => Err(CompileError::InvalidArg),
+        }
+    }
+}
+
+fn value_to_expr(v: Value) -> Expr {
+    match v {
+        Value::Int(value) => Expr::IntLiteral { value },
+        Value::Text(value) => Expr::StringLiteral { value },
+        Value::Flag(b) => Expr::IntLiteral { value: b as i64 },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn script(drone: u32, statements: serde_json::Value) -> Script {
+        Script::new(
+            serde_json::from_value(json!({
+                "version": 1,
+                "node": "Program",
+                "drone": drone,
+                "statements": statements
+            }))
+            .unwrap(),
+        )
+    }
+
+    fn call(func: &str, args: serde_json::Value) -> serde_json::Value {
+        json!({ "node": "ExprStmt", "expr": { "node": "Call", "func": func, "args": args } })
+    }
+
+    #[test]
+    fn scripts_wait_on_flags_and_messages() {
+        let tasks = TaskManager::new();
+        let mut board = Blackboard::new();
+        let mut sender = script(
+            1,
+            json!([
+                call(
+                    "send",
+                    json!([
+                        { "node": "IntLiteral", "value": 2 },
+                        { "node": "IntLiteral", "value": 5 }
+                    ])
+                ),
+                call(
+                    "set_flag",
+                    json!([{ "node": "StringLiteral", "value": "ready" }])
+                )
+            ]),
+        );
+        let mut echo = script(
+            2,
+            json!([
+                call("wait_for", json!([{ "node": "StringLiteral", "value": "ready" }])),
+                {
+                    "node": "Let", "name": "n", "ty": "Value",
+                    "value": { "node": "Call", "func": "recv", "args": [] }
+                },
+                call("send", json!([
+                    { "node": "IntLiteral", "value": 1 },
+                    { "node": "VarRef", "name": "n" }
+                ]))
+            ]),
+        );
+
+        // Waiting stays put across steps instead of spinning
+        for _ in 0..3 {
+            assert!(echo.step(&tasks, &mut board).is_none());
+            assert_eq!(
+                echo.status,
+                ScriptStatus::Waiting("Waiting for ready".into())
+            );
+        }
+        assert!(sender.step(&tasks, &mut board).is_none());
+        assert_eq!(sender.status, ScriptStatus::Done);
+        assert!(echo.step(&tasks, &mut board).is_none());
+        assert_eq!(echo.status, ScriptStatus::Done);
+        let reply = board.recv(1).unwrap();
+        assert_eq!((reply.from, reply.value), (Some(2), Value::Int(5)));
+    }
+}
@@ -0,0 +1,165 @@
+use serde::{Deserialize, Serialize};
+
+use crate::resources::Resources;
+
+// Where a game is in its life, as the player sees it. `Paused` is a
+// running game whose clock is paused; only the other states are saved.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub enum SessionState {
+    // Before the first tick, while the player places zones and orders
+    #[default]
+    Setup,
+    Running,
+    Paused,
+    Won,
+    Lost,
+}
+
+// The part of `SessionState` a save holds, so no save can claim a pause
+// that the clock knows nothing about.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+enum Phase {
+    #[default]
+    Setup,
+    Running,
+    Won,
+    Lost,
+}
+
+impl From<Phase> for SessionState {
+    fn from(phase: Phase) -> Self {
+        match phase {
+            Phase::Setup => SessionState::Setup,
+            Phase::Running => SessionState::Running,
+            Phase::Won => SessionState::Won,
+            Phase::Lost => SessionState::Lost,
+        }
+    }
+}
+
+impl SessionState {
+    pub fn is_over(self) -> bool {
+        matches!(self, SessionState::Won | SessionState::Lost)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SessionState::Setup => "Setup",
+            SessionState::Running => "Running",
+            SessionState::Paused => "Paused",
+            SessionState::Won => "Victory",
+            SessionState::Lost => "Defeat",
+        }
+    }
+}
+
+// One way to win; meeting any of a scenario's conditions wins the game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum VictoryCondition {
+    SurviveWaves { waves: u32 },
+    CollectIron { amount: u32 },
+}
+
+impl VictoryCondition {
+    // e.g. "survive 3 waves".
+    pub fn describe(&self) -> String {
+        match self {
+            VictoryCondition::SurviveWaves { waves: 1 } => "survive 1 wave".to_string(),
+            VictoryCondition::SurviveWaves { waves } => format!("survive {} waves", waves),
+            VictoryCondition::CollectIron { amount } => format!("collect {} iron", amount),
+        }
+    }
+
+    fn is_met(&self, waves_survived: u32, resources: &Resources) -> bool {
+        match *self {
+            VictoryCondition::SurviveWaves { waves } => waves_survived >= waves,
+            VictoryCondition::CollectIron { amount } => resources.iron >= amount,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Session {
+    state: Phase,
+    pub victory: Vec<VictoryCondition>,
+}
+
+impl Session {
+    pub fn new(victory: Vec<VictoryCondition>) -> Self {
+        Self {
+            state: Phase::Setup,
+            victory,
+        }
+    }
+
+    pub fn state(&self) -> SessionState {
+        self.state.into()
+    }
+
+    // Setup -> Running; false if the game had already started.
+    pub(crate) fn start(&mut self) -> bool {
+        let starting = self.state == Phase::Setup;
+        if starting {
+            self.state = Phase::Running;
+        }
+        starting
+    }
+
+    // Ends a running game once the core is destroyed or any victory
+    // condition holds. Losing wins ties: a core destroyed by the last wave
+    // doesn't count as surviving it.
+    pub(crate) fn check(&mut self, core_hp: u32, waves_survived: u32, resources: &Resources) {
+        if self.state != Phase::Running {
+            return;
+        }
+        if core_hp == 0 {
+            self.state = Phase::Lost;
+        } else if self
+            .victory
+            .iter()
+            .any(|v| v.is_met(waves_survived, resources))
+        {
+            self.state = Phase::Won;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn games_end_once_and_losing_wins_ties() {
+        let iron = |iron| Resources { stone: 0, iron };
+        let mut session = Session::new(vec![
+            VictoryCondition::SurviveWaves { waves: 2 },
+            VictoryCondition::CollectIron { amount: 50 },
+        ]);
+        session.check(0, 0, &iron(0));
+        assert_eq!(session.state(), SessionState::Setup);
+
+        assert!(session.start());
+        assert!(!session.start());
+        session.check(100, 1, &iron(49));
+        assert_eq!(session.state(), SessionState::Running);
+        session.check(100, 1, &iron(50));
+        assert_eq!(session.state(), SessionState::Won);
+        // Over is over
+        session.check(0, 1, &iron(0));
+        assert_eq!(session.state(), SessionState::Won);
+
+        let mut session = Session::new(vec![VictoryCondition::SurviveWaves { waves: 2 }]);
+        session.start();
+        session.check(0, 2, &iron(0));
+        assert_eq!(session.state(), SessionState::Lost);
+    }
+
+    #[test]
+    fn saved_sessions_are_never_paused() {
+        let saved = serde_json::to_value(Session::new(Vec::new())).unwrap();
+        assert_eq!(saved["state"], "Setup");
+        let paused = serde_json::json!({ "state": "Paused", "victory": [] });
+        assert!(serde_json::from_value::<Session>(paused).is_err());
+    }
+}
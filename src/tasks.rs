@@ -1,43 +1,270 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
 use crate::coords::{TileBox3, TileCoord3};
+use crate::drones::Cargo;
+use crate::resources::Resources;
+use crate::tile::TileKind;
 use crate::world::World;
 
+// Index into `TaskManager::tasks`; tasks are never removed, so ids stay stable.
+pub type TaskId = usize;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum TaskState {
     Pending,
     InProgress,
     Done,
+    Cancelled,
+}
+
+// Broad kind of work, used for drone roles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TaskKind {
+    Mine,
+    Build,
+    Haul,
+    Move,
+    Guard,
+    Fabricate,
+    Repair,
+}
+
+impl TaskKind {
+    pub fn label(self) -> &'static str {
+        match self {
+            TaskKind::Mine => "Mining",
+            TaskKind::Build => "Building",
+            TaskKind::Haul => "Hauling",
+            TaskKind::Move => "Moving",
+            TaskKind::Guard => "Guarding",
+            TaskKind::Fabricate => "Fabricating",
+            TaskKind::Repair => "Repairing",
+        }
+    }
+
+    // Accepts the verb, the activity or the role name ("mine", "mining", "miner").
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "mine" | "mining" | "miner" => Some(TaskKind::Mine),
+            "build" | "building" | "builder" => Some(TaskKind::Build),
+            "haul" | "hauling" | "hauler" => Some(TaskKind::Haul),
+            "move" | "moving" | "mover" => Some(TaskKind::Move),
+            "guard" | "guarding" => Some(TaskKind::Guard),
+            "repair" | "repairing" | "mechanic" => Some(TaskKind::Repair),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum Task {
     MineBox(TileBox3),
+    BuildWall(TileBox3),
+    BuildFloor(TileBox3),
+    BuildOnBorder(TileBox3),
+    BuildCharger(TileBox3),
+    Haul(TileBox3),
+    MoveTo(TileCoord3),
+    Patrol(Vec<TileCoord3>),
+    Guard(TileBox3),
+    // Built by the core rather than a drone: a new drone of the named type
+    Fabricate(String),
+    // Fly to a broken drone, by id, and fix it for `REPAIR_COST`
+    Repair(u32),
 }
 
+// Iron spent on each repair, whether by a drone or the core.
+pub const REPAIR_COST: Resources = Resources { stone: 0, iron: 2 };
+
 impl Task {
     pub fn description(&self) -> String {
         match self {
-            Task::MineBox(b) => format!(
-                "Mine box (({},{},{})->({},{},{}))",
-                b.min.x, b.min.y, b.min.z, b.max.x, b.max.y, b.max.z
-            ),
+            Task::MineBox(b) => format!("Mine box ({})", box_label(b)),
+            Task::BuildWall(b) => format!("Build wall ({})", box_label(b)),
+            Task::BuildFloor(b) => format!("Build floor ({})", box_label(b)),
+            Task::BuildOnBorder(b) => format!("Build wall on border ({})", box_label(b)),
+            Task::BuildCharger(b) => format!("Build charger ({})", box_label(b)),
+            Task::Haul(b) => format!("Haul items ({})", box_label(b)),
+            Task::MoveTo(c) => format!("Move to ({},{},{})", c.x, c.y, c.z),
+            Task::Patrol(points) => {
+                let stops: Vec<_> = points
+                    .iter()
+                    .map(|c| format!("({},{},{})", c.x, c.y, c.z))
+                    .collect();
+                format!("Patrol {}", stops.join("->"))
+            }
+            Task::Guard(b) => format!("Guard area ({})", box_label(b)),
+            Task::Fabricate(name) => format!("Fabricate {}", name.to_lowercase()),
+            Task::Repair(id) => format!("Repair drone #{}", id),
+        }
+    }
+
+    pub fn kind(&self) -> TaskKind {
+        match self {
+            Task::MineBox(_) => TaskKind::Mine,
+            Task::BuildWall(_)
+            | Task::BuildFloor(_)
+            | Task::BuildOnBorder(_)
+            | Task::BuildCharger(_) => TaskKind::Build,
+            Task::Haul(_) => TaskKind::Haul,
+            Task::MoveTo(_) | Task::Patrol(_) => TaskKind::Move,
+            Task::Guard(_) => TaskKind::Guard,
+            Task::Fabricate(_) => TaskKind::Fabricate,
+            Task::Repair(_) => TaskKind::Repair,
+        }
+    }
+
+    pub fn area(&self) -> Option<TileBox3> {
+        match self {
+            Task::MineBox(b)
+            | Task::BuildWall(b)
+            | Task::BuildFloor(b)
+            | Task::BuildOnBorder(b)
+            | Task::BuildCharger(b)
+            | Task::Haul(b)
+            | Task::Guard(b) => Some(*b),
+            Task::MoveTo(_) | Task::Patrol(_) | Task::Fabricate(_) | Task::Repair(_) => None,
+        }
+    }
+
+    // Tiles the task needs exclusive access to while it runs.
+    pub fn footprint(&self) -> Vec<TileCoord3> {
+        match self {
+            Task::MineBox(b) | Task::BuildWall(b) | Task::BuildFloor(b) | Task::BuildCharger(b) => {
+                b.iter_tiles().collect()
+            }
+            Task::BuildOnBorder(b) => b.border_tiles().collect(),
+            // Hauling and movement never change tiles
+            Task::Haul(_)
+            | Task::MoveTo(_)
+            | Task::Patrol(_)
+            | Task::Guard(_)
+            | Task::Fabricate(_)
+            | Task::Repair(_) => Vec::new(),
+        }
+    }
+
+    fn build_kind(&self) -> Option<TileKind> {
+        match self {
+            Task::BuildWall(_) | Task::BuildOnBorder(_) => Some(TileKind::Wall),
+            Task::BuildFloor(_) => Some(TileKind::Floor),
+            Task::BuildCharger(_) => Some(TileKind::Charger),
+            _ => None,
+        }
+    }
+
+    // Mining and hauling fill the drone's cargo as they go.
+    pub fn gathers(&self) -> bool {
+        matches!(self, Task::MineBox(_) | Task::Haul(_))
+    }
+
+    // Patrols and guard duties keep a drone busy until they are cancelled.
+    pub fn is_standing(&self) -> bool {
+        matches!(self, Task::Patrol(_) | Task::Guard(_))
+    }
+
+    // False while the task could not make any progress, e.g. a build that
+    // can't afford a single tile. Such tasks are skipped by the scheduler.
+    pub fn is_ready(&self, world: &World) -> bool {
+        if let Task::Haul(_) = self {
+            return world.core().is_some() || !world.stockpiles().is_empty();
+        }
+        if let Task::Repair(_) = self {
+            return world.resources.can_afford(&REPAIR_COST);
+        }
+        match self.build_kind().and_then(|k| k.build_cost()) {
+            Some(cost) => world.resources.can_afford(&cost),
+            None => true,
+        }
+    }
+
+    // Tiles a drone still has to visit and work on, in footprint order:
+    // mineable tiles, tiles a build can go over, or piles left to haul.
+    // Movement tasks have none; the engine steers those directly.
+    pub fn work_tiles(&self, world: &World) -> Vec<TileCoord3> {
+        if let Task::Haul(b) = self {
+            let mut piles: Vec<_> = world
+                .ground_items()
+                .map(|(c, _)| c)
+                .filter(|c| b.contains(*c))
+                .collect();
+            piles.sort_by_key(|c| (c.z, c.y, c.x));
+            return piles;
+        }
+        let build_kind = self.build_kind();
+        self.footprint()
+            .into_iter()
+            .filter(|c| match (world.get_tile(*c), build_kind) {
+                (Some(k), Some(target)) => k.can_build_over(target),
+                (Some(k), None) => k.is_mineable(),
+                (None, _) => false,
+            })
+            .collect()
+    }
+
+    // True once nothing in the footprint is left to mine or build, or
+    // nothing in the area is left to haul. Movement tasks depend on where
+    // the drone is, fabrication on the core and repairs on another drone,
+    // so none of them is ever satisfied by the world alone.
+    pub fn is_satisfied(&self, world: &World) -> bool {
+        match self {
+            Task::MoveTo(_)
+            | Task::Patrol(_)
+            | Task::Guard(_)
+            | Task::Fabricate(_)
+            | Task::Repair(_) => false,
+            _ => self.work_tiles(world).is_empty(),
         }
     }
 }
 
-#[derive(Debug, Default)]
+fn box_label(b: &TileBox3) -> String {
+    format!(
+        "({},{},{})->({},{},{})",
+        b.min.x, b.min.y, b.min.z, b.max.x, b.max.y, b.max.z
+    )
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct TaskManager {
     pub tasks: Vec<(Task, TaskState)>,
+    // Why a pending task is currently waiting, for display
+    blocked: BTreeMap<TaskId, String>,
+    // Tasks addressed to one drone by id; others may be taken by any drone
+    assigned: BTreeMap<TaskId, u32>,
 }
 
 impl TaskManager {
     pub fn new() -> Self {
-        Self { tasks: Vec::new() }
+        Self {
+            tasks: Vec::new(),
+            blocked: BTreeMap::new(),
+            assigned: BTreeMap::new(),
+        }
     }
 
-    pub fn push(&mut self, task: Task) {
+    pub fn push(&mut self, task: Task) -> TaskId {
         self.tasks.push((task, TaskState::Pending));
+        self.tasks.len() - 1
+    }
+
+    pub fn get(&self, id: TaskId) -> Option<&Task> {
+        self.tasks.get(id).map(|(t, _)| t)
+    }
+
+    pub fn state(&self, id: TaskId) -> Option<TaskState> {
+        self.tasks.get(id).map(|(_, s)| *s)
+    }
+
+    pub fn pending_ids(&self) -> Vec<TaskId> {
+        self.tasks
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, s))| *s == TaskState::Pending)
+            .map(|(id, _)| id)
+            .collect()
     }
 
     pub fn any_pending(&self) -> bool {
@@ -62,35 +289,135 @@ impl TaskManager {
             *state = TaskState::Done;
         }
     }
+
+    pub fn start(&mut self, id: TaskId) -> Option<Task> {
+        match self.tasks.get_mut(id) {
+            Some((task, state)) if *state == TaskState::Pending => {
+                *state = TaskState::InProgress;
+                self.blocked.remove(&id);
+                Some(task.clone())
+            }
+            _ => None,
+        }
+    }
+
+    pub fn complete(&mut self, id: TaskId) {
+        if let Some((_, state)) = self.tasks.get_mut(id) {
+            *state = TaskState::Done;
+            self.blocked.remove(&id);
+        }
+    }
+
+    // Puts a started task back in the queue, e.g. when it ran out of resources.
+    pub fn requeue(&mut self, id: TaskId, reason: impl Into<String>) {
+        if let Some((_, state)) = self.tasks.get_mut(id)
+            && *state == TaskState::InProgress
+        {
+            *state = TaskState::Pending;
+            self.blocked.insert(id, reason.into());
+        }
+    }
+
+    // Records why a pending task could not be started this tick.
+    pub fn set_blocked(&mut self, id: TaskId, reason: impl Into<String>) {
+        if self.state(id) == Some(TaskState::Pending) {
+            self.blocked.insert(id, reason.into());
+        }
+    }
+
+    pub fn blocked_reason(&self, id: TaskId) -> Option<&str> {
+        self.blocked.get(&id).map(String::as_str)
+    }
+
+    pub fn assign(&mut self, id: TaskId, drone_id: u32) {
+        if id < self.tasks.len() {
+            self.assigned.insert(id, drone_id);
+        }
+    }
+
+    pub fn assigned_drone(&self, id: TaskId) -> Option<u32> {
+        self.assigned.get(&id).copied()
+    }
+
+    // Only tasks that have not finished can be cancelled.
+    pub fn cancel(&mut self, id: TaskId) -> bool {
+        match self.tasks.get_mut(id) {
+            Some((_, state)) if matches!(state, TaskState::Pending | TaskState::InProgress) => {
+                *state = TaskState::Cancelled;
+                self.blocked.remove(&id);
+                true
+            }
+            _ => false,
+        }
+    }
 }
 
-pub fn apply_task(world: &mut World, task: &Task) -> u32 {
+// Works a single tile of the task for one tick. Mining deals
+// `mining_power` damage and only yields once the tile gives way; mined
+// and picked-up items go into `cargo`, and whatever doesn't fit stays on
+// the tile. Returns false when nothing was done, e.g. the stockpile can't
+// pay for the next wall.
+pub fn apply_task_at(
+    world: &mut World,
+    task: &Task,
+    c: TileCoord3,
+    cargo: &mut Cargo,
+    mining_power: u32,
+) -> bool {
     match task {
-        Task::MineBox(b) => {
-            let mut count = 0u32;
-            for c in b.iter_tiles() {
-                if world
-                    .mine_tile(TileCoord3 {
-                        x: c.x,
-                        y: c.y,
-                        z: c.z,
-                    })
-                    .is_some()
-                {
-                    count = count.saturating_add(1);
-                }
+        Task::MineBox(_) => {
+            if mining_power == 0 || !world.get_tile(c).is_some_and(|k| k.is_mineable()) {
+                return false;
             }
-            count
+            if let Some(y) = world.dig_tile(c, mining_power) {
+                let left = cargo.load(y.as_resources());
+                world.drop_items(c, left);
+            }
+            true
+        }
+        Task::BuildWall(_)
+        | Task::BuildFloor(_)
+        | Task::BuildOnBorder(_)
+        | Task::BuildCharger(_) => task
+            .build_kind()
+            .is_some_and(|kind| world.build_tile(c, kind)),
+        Task::Haul(_) => {
+            let items = world.take_items(c);
+            let left = cargo.load(items);
+            world.drop_items(c, left);
+            left != items
         }
+        Task::MoveTo(_)
+        | Task::Patrol(_)
+        | Task::Guard(_)
+        | Task::Fabricate(_)
+        | Task::Repair(_) => false,
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::tile::TileKind;
     use crate::world::World;
 
+    // Works every remaining tile at once, stopping at the first one that
+    // fails. Returns how many tiles were worked. No drone is involved, so
+    // tiles are mined outright and gathered items go straight to the
+    // nearest drop-off, or stay where they were if there is none.
+    fn apply_task(world: &mut World, task: &Task) -> u32 {
+        let mut count = 0u32;
+        for c in task.work_tiles(world) {
+            let mut cargo = Cargo::new(u32::MAX);
+            if !apply_task_at(world, task, c, &mut cargo, u32::MAX) {
+                break;
+            }
+            let dest = world.nearest_drop_off(c).unwrap_or(c);
+            world.drop_items(dest, cargo.unload());
+            count = count.saturating_add(1);
+        }
+        count
+    }
+
     #[test]
     fn task_description() {
         let t = Task::MineBox(TileBox3::new(
@@ -115,6 +442,33 @@ mod tests {
         assert!(!tm.any_pending());
     }
 
+    #[test]
+    fn task_kind_names() {
+        assert_eq!(TaskKind::from_name("miner"), Some(TaskKind::Mine));
+        assert_eq!(TaskKind::from_name("Building"), Some(TaskKind::Build));
+        assert_eq!(TaskKind::from_name("haul"), Some(TaskKind::Haul));
+        assert_eq!(TaskKind::from_name("guard"), Some(TaskKind::Guard));
+        assert_eq!(TaskKind::from_name("dance"), None);
+    }
+
+    #[test]
+    fn task_manager_ids_and_cancel() {
+        let mut tm = TaskManager::new();
+        let t = Task::MineBox(TileBox3::new(
+            TileCoord3::new(0, 0, 0),
+            TileCoord3::new(0, 0, 0),
+        ));
+        let a = tm.push(t.clone());
+        let b = tm.push(t);
+        assert_eq!((a, b), (0, 1));
+        assert!(tm.cancel(a));
+        assert_eq!(tm.state(a), Some(TaskState::Cancelled));
+        assert_eq!(tm.pending_ids(), vec![b]);
+        assert!(tm.start(b).is_some());
+        tm.complete(b);
+        assert!(!tm.cancel(b));
+    }
+
     #[test]
     fn apply_mine_task_counts_mined_tiles() {
         let mut world = World::new(2, 2, 1, TileKind::Stone);
@@ -124,6 +478,47 @@ mod tests {
         ));
         let mined = apply_task(&mut world, &t);
         assert_eq!(mined, 4);
-        assert_eq!(world.resources.stone, 4);
+        assert_eq!(world.ground_total().stone, 4);
+    }
+
+    #[test]
+    fn haul_moves_piles_to_stockpile() {
+        let mut world = World::new(3, 1, 1, TileKind::Stone);
+        let area = TileBox3::new(TileCoord3::new(0, 0, 0), TileCoord3::new(1, 0, 0));
+        apply_task(&mut world, &Task::MineBox(area));
+        let haul = Task::Haul(area);
+        assert!(!haul.is_ready(&world));
+
+        let spot = TileCoord3::new(2, 0, 0);
+        world.set_tile(spot, TileKind::Floor);
+        world.add_stockpile(TileBox3::new(spot, spot));
+        assert!(haul.is_ready(&world));
+        assert_eq!(apply_task(&mut world, &haul), 2);
+        assert!(haul.is_satisfied(&world));
+        assert_eq!(world.resources.stone, 2);
+    }
+
+    #[test]
+    fn build_on_border_stops_when_resources_run_out() {
+        let mut world = World::new(3, 3, 1, TileKind::Air);
+        world.resources.add_stone(10);
+        let t = Task::BuildOnBorder(TileBox3::new(
+            TileCoord3::new(0, 0, 0),
+            TileCoord3::new(2, 2, 0),
+        ));
+        assert!(t.is_ready(&world));
+        // 8 border tiles at 2 stone each, only enough for 5
+        assert_eq!(apply_task(&mut world, &t), 5);
+        assert_eq!(world.resources.stone, 0);
+        assert_eq!(
+            world.get_tile(TileCoord3::new(1, 1, 0)),
+            Some(TileKind::Air)
+        );
+        assert!(!t.is_ready(&world));
+        assert!(!t.is_satisfied(&world));
+
+        world.resources.add_stone(6);
+        assert_eq!(apply_task(&mut world, &t), 3);
+        assert!(t.is_satisfied(&world));
     }
 }
@@ -1,3 +1,5 @@
+use crate::resources::Resources;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
 pub enum TileKind {
     Air,
@@ -5,6 +7,10 @@ pub enum TileKind {
     Iron,
     Wall,
     Floor,
+    // Stair/ramp tile: the only way between levels
+    Stairs,
+    // Drones standing here recharge their batteries
+    Charger,
 }
 
 impl TileKind {
@@ -12,6 +18,24 @@ impl TileKind {
         matches!(self, TileKind::Stone | TileKind::Iron)
     }
 
+    // Damage a tile takes before it gives way; a drone deals its mining
+    // power each tick. Zero for tiles that can't be mined.
+    pub fn hardness(self) -> u8 {
+        match self {
+            TileKind::Stone => 2,
+            TileKind::Iron => 4,
+            _ => 0,
+        }
+    }
+
+    // Drones may stand on and walk through these tiles.
+    pub fn is_passable(self) -> bool {
+        matches!(
+            self,
+            TileKind::Air | TileKind::Floor | TileKind::Stairs | TileKind::Charger
+        )
+    }
+
     pub fn mined_yield(self) -> Option<ResourceYield> {
         match self {
             TileKind::Stone => Some(ResourceYield::Stone(1)),
@@ -19,6 +43,25 @@ impl TileKind {
             _ => None,
         }
     }
+
+    // Resources spent per tile when a drone builds this kind.
+    pub fn build_cost(self) -> Option<Resources> {
+        match self {
+            TileKind::Wall => Some(Resources { stone: 2, iron: 0 }),
+            TileKind::Floor => Some(Resources { stone: 1, iron: 0 }),
+            TileKind::Charger => Some(Resources { stone: 2, iron: 2 }),
+            _ => None,
+        }
+    }
+
+    // Whether a tile of this kind may be replaced by building `target`.
+    pub fn can_build_over(self, target: TileKind) -> bool {
+        match target {
+            TileKind::Wall | TileKind::Charger => matches!(self, TileKind::Air | TileKind::Floor),
+            TileKind::Floor => self == TileKind::Air,
+            _ => false,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -27,6 +70,15 @@ pub enum ResourceYield {
     Iron(u32),
 }
 
+impl ResourceYield {
+    pub fn as_resources(self) -> Resources {
+        match self {
+            ResourceYield::Stone(n) => Resources { stone: n, iron: 0 },
+            ResourceYield::Iron(n) => Resources { stone: 0, iron: n },
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -38,6 +90,17 @@ mod tests {
         assert!(!TileKind::Air.is_mineable());
         assert!(!TileKind::Wall.is_mineable());
         assert!(!TileKind::Floor.is_mineable());
+        assert!(!TileKind::Stairs.is_mineable());
+    }
+
+    #[test]
+    fn passability() {
+        assert!(TileKind::Air.is_passable());
+        assert!(TileKind::Floor.is_passable());
+        assert!(TileKind::Stairs.is_passable());
+        assert!(!TileKind::Stone.is_passable());
+        assert!(!TileKind::Iron.is_passable());
+        assert!(!TileKind::Wall.is_passable());
     }
 
     #[test]
@@ -45,5 +108,19 @@ mod tests {
         assert_eq!(TileKind::Stone.mined_yield(), Some(ResourceYield::Stone(1)));
         assert_eq!(TileKind::Iron.mined_yield(), Some(ResourceYield::Iron(1)));
         assert_eq!(TileKind::Air.mined_yield(), None);
+        assert!(TileKind::Iron.hardness() > TileKind::Stone.hardness());
+        assert_eq!(TileKind::Air.hardness(), 0);
+    }
+
+    #[test]
+    fn build_rules() {
+        assert!(TileKind::Wall.build_cost().is_some());
+        assert!(TileKind::Floor.build_cost().is_some());
+        assert!(TileKind::Stone.build_cost().is_none());
+        assert!(TileKind::Air.can_build_over(TileKind::Wall));
+        assert!(TileKind::Floor.can_build_over(TileKind::Wall));
+        assert!(!TileKind::Stone.can_build_over(TileKind::Wall));
+        assert!(!TileKind::Floor.can_build_over(TileKind::Floor));
+        assert!(TileKind::Floor.can_build_over(TileKind::Charger));
     }
 }
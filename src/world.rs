@@ -1,18 +1,76 @@
+use std::collections::{HashMap, HashSet};
+
 use rand::{Rng, SeedableRng, rngs::StdRng};
+use serde::{Deserialize, Serialize};
 
-use crate::coords::TileCoord3;
+use crate::coords::{TileBox3, TileCoord3};
 use crate::resources::Resources;
 use crate::tile::{ResourceYield, TileKind};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct World {
     width: i32,
     height: i32,
     levels: i32,
+    #[serde(with = "tile_runs")]
     tiles: Vec<TileKind>,
+    // Stockpiled totals: items only count here once they reach a stockpile zone
     pub resources: Resources,
+    // Loose item piles lying outside stockpiles
+    #[serde(with = "crate::coords::coord_map")]
+    ground_items: HashMap<TileCoord3, Resources>,
+    stockpiles: Vec<TileBox3>,
+    core: Option<TileCoord3>,
     core_hp: u32,
     core_hp_max: u32,
+    // Bumped whenever a tile changes how drones can move through it, so
+    // cached paths can tell when they are stale
+    revision: u64,
+    // Tiles behind the last few revisions, oldest first: entry i was
+    // revision `changes_start + i + 1`
+    changes: Vec<TileCoord3>,
+    changes_start: u64,
+    chargers: Vec<TileCoord3>,
+    // Partly mined tiles and the damage they have taken so far
+    #[serde(with = "crate::coords::coord_map")]
+    damage: HashMap<TileCoord3, u8>,
+    // Tiles that changed kind or took damage since the last `take_edits`
+    #[serde(skip)]
+    edited: Vec<TileCoord3>,
+    // Tiles overwritten since the last `take_journal`, by index and with
+    // their old kind; None unless the engine keeps a history
+    #[serde(skip)]
+    journal: Option<Vec<(u32, TileKind)>>,
+}
+
+// Older entries are dropped; consumers that fall further behind rebuild.
+const MAX_TRACKED_CHANGES: usize = 4096;
+
+// Tiles are saved as (kind, count) runs; maps are mostly long stretches of
+// the same rock.
+mod tile_runs {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    use crate::tile::TileKind;
+
+    pub fn serialize<S: Serializer>(tiles: &[TileKind], s: S) -> Result<S::Ok, S::Error> {
+        let mut runs: Vec<(TileKind, u32)> = Vec::new();
+        for &k in tiles {
+            match runs.last_mut() {
+                Some((kind, n)) if *kind == k => *n += 1,
+                _ => runs.push((k, 1)),
+            }
+        }
+        runs.serialize(s)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Vec<TileKind>, D::Error> {
+        let runs: Vec<(TileKind, u32)> = Vec::deserialize(d)?;
+        Ok(runs
+            .into_iter()
+            .flat_map(|(k, n)| std::iter::repeat_n(k, n as usize))
+            .collect())
+    }
 }
 
 impl World {
@@ -25,21 +83,44 @@ impl World {
             levels,
             tiles: vec![fill; size],
             resources: Resources::default(),
+            ground_items: HashMap::new(),
+            stockpiles: Vec::new(),
+            core: None,
             core_hp: core_hp_max,
             core_hp_max,
+            revision: 0,
+            changes: Vec::new(),
+            changes_start: 0,
+            chargers: Vec::new(),
+            damage: HashMap::new(),
+            edited: Vec::new(),
+            journal: None,
         }
     }
 
     pub fn from_seed_with_distribution(width: i32, height: i32, levels: i32, seed: u64) -> Self {
+        Self::generate(width, height, levels, seed, 0.10, 0.45)
+    }
+
+    // Random rock: each tile is iron or stone with the given chances, air
+    // otherwise.
+    pub fn generate(
+        width: i32,
+        height: i32,
+        levels: i32,
+        seed: u64,
+        iron_share: f32,
+        stone_share: f32,
+    ) -> Self {
         let mut world = Self::new(width, height, levels, TileKind::Air);
         let mut rng = StdRng::seed_from_u64(seed);
         for z in 0..levels {
             for y in 0..height {
                 for x in 0..width {
                     let roll: f32 = rng.r#gen();
-                    let kind = if roll < 0.10 {
+                    let kind = if roll < iron_share {
                         TileKind::Iron
-                    } else if roll < 0.55 {
+                    } else if roll < iron_share + stone_share {
                         TileKind::Stone
                     } else {
                         TileKind::Air
@@ -60,9 +141,94 @@ impl World {
     pub fn levels(&self) -> i32 {
         self.levels
     }
+    pub fn core(&self) -> Option<TileCoord3> {
+        self.core
+    }
+    pub fn set_core(&mut self, c: TileCoord3) {
+        self.core = Some(c);
+    }
     pub fn core_hp(&self) -> (u32, u32) {
         (self.core_hp, self.core_hp_max)
     }
+    pub fn damage_core(&mut self, amount: u32) {
+        self.core_hp = self.core_hp.saturating_sub(amount);
+    }
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+    // Tiles changed after `revision`, or None if the log no longer reaches
+    // back that far.
+    pub fn changes_since(&self, revision: u64) -> Option<&[TileCoord3]> {
+        let skip = revision.checked_sub(self.changes_start)? as usize;
+        self.changes.get(skip..)
+    }
+
+    // Stable fingerprint of the map, see `hash::StateHashes`.
+    pub fn state_hash(&self) -> u64 {
+        crate::hash::hash_of(self)
+    }
+
+    // False when the tile grid doesn't match the dimensions, e.g. a
+    // damaged save file.
+    pub fn is_well_formed(&self) -> bool {
+        self.width > 0
+            && self.height > 0
+            && self.levels > 0
+            && self.tiles.len() as i64
+                == i64::from(self.width) * i64::from(self.height) * i64::from(self.levels)
+    }
+
+    // Every tile that looks different since the last call, once each and
+    // in the order they first changed.
+    pub fn take_edits(&mut self) -> Vec<TileCoord3> {
+        let mut seen = HashSet::new();
+        let mut edits = std::mem::take(&mut self.edited);
+        edits.retain(|c| seen.insert(*c));
+        edits
+    }
+
+    pub(crate) fn set_journaling(&mut self, on: bool) {
+        self.journal = on.then(Vec::new);
+    }
+
+    pub(crate) fn take_journal(&mut self) -> Vec<(u32, TileKind)> {
+        self.journal
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    // Puts back the old kinds from a journal, newest entry first. Only the
+    // grid changes; the caller restores everything else from a snapshot.
+    pub(crate) fn undo_journal(&mut self, entries: &[(u32, TileKind)]) {
+        for &(i, old) in entries.iter().rev() {
+            let i = i as usize;
+            self.tiles[i] = old;
+            let (w, h) = (self.width as usize, self.height as usize);
+            self.edited.push(TileCoord3::new(
+                (i % w) as i32,
+                (i / w % h) as i32,
+                (i / (w * h)) as i32,
+            ));
+        }
+    }
+
+    // A copy without the tile grid, for snapshots that track it separately.
+    pub(crate) fn without_tiles(&mut self) -> World {
+        let tiles = std::mem::take(&mut self.tiles);
+        let journal = self.journal.take();
+        let copy = self.clone();
+        self.tiles = tiles;
+        self.journal = journal;
+        copy
+    }
+
+    // Moves the grid and its pending edits over from `other`, e.g. to put a
+    // rolled-back grid under a snapshot saved without one.
+    pub(crate) fn take_tiles_from(&mut self, other: &mut World) {
+        self.tiles = std::mem::take(&mut other.tiles);
+        self.edited.append(&mut other.edited);
+    }
 
     fn index(&self, c: TileCoord3) -> Option<usize> {
         if c.x < 0
@@ -84,24 +250,179 @@ impl World {
 
     pub fn set_tile(&mut self, c: TileCoord3, k: TileKind) {
         if let Some(i) = self.index(c) {
-            self.tiles[i] = k;
+            self.replace_tile(c, i, k);
         }
     }
 
+    pub fn is_passable(&self, c: TileCoord3) -> bool {
+        self.get_tile(c).is_some_and(|k| k.is_passable())
+    }
+
+    // Every charger tile, in the order they were placed.
+    pub fn chargers(&self) -> &[TileCoord3] {
+        &self.chargers
+    }
+
+    fn replace_tile(&mut self, c: TileCoord3, i: usize, k: TileKind) {
+        let old = self.tiles[i];
+        let is_stairs = |k: TileKind| k == TileKind::Stairs;
+        if old.is_passable() != k.is_passable() || is_stairs(old) != is_stairs(k) {
+            self.revision += 1;
+            if self.changes.len() == MAX_TRACKED_CHANGES {
+                let dropped = MAX_TRACKED_CHANGES / 2;
+                self.changes.drain(..dropped);
+                self.changes_start += dropped as u64;
+            }
+            self.changes.push(c);
+        }
+        if old == TileKind::Charger {
+            self.chargers.retain(|t| *t != c);
+        }
+        if k == TileKind::Charger {
+            self.chargers.push(c);
+        }
+        if old != k {
+            self.damage.remove(&c);
+            self.edited.push(c);
+            if let Some(journal) = &mut self.journal {
+                journal.push((i as u32, old));
+            }
+        }
+        self.tiles[i] = k;
+    }
+
+    // Places `k` when the tile can be built over and resources cover the cost.
+    pub fn build_tile(&mut self, c: TileCoord3, k: TileKind) -> bool {
+        let Some(i) = self.index(c) else {
+            return false;
+        };
+        let Some(cost) = k.build_cost() else {
+            return false;
+        };
+        if !self.tiles[i].can_build_over(k) || !self.resources.try_spend(&cost) {
+            return false;
+        }
+        self.replace_tile(c, i, k);
+        true
+    }
+
+    // Clears a mineable tile and hands back its yield; the miner decides
+    // where the items go.
     pub fn mine_tile(&mut self, c: TileCoord3) -> Option<ResourceYield> {
         let i = self.index(c)?;
-        let k = self.tiles[i];
-        if let Some(y) = k.mined_yield() {
-            self.tiles[i] = TileKind::Air;
-            match y {
-                ResourceYield::Stone(n) => self.resources.add_stone(n),
-                ResourceYield::Iron(n) => self.resources.add_iron(n),
-            }
-            Some(y)
+        let y = self.tiles[i].mined_yield()?;
+        self.replace_tile(c, i, TileKind::Air);
+        Some(y)
+    }
+
+    // Chips `power` off a mineable tile. Returns the yield once the damage
+    // reaches the tile's hardness; until then the tile stays, partly mined.
+    pub fn dig_tile(&mut self, c: TileCoord3, power: u32) -> Option<ResourceYield> {
+        let hardness = self.get_tile(c).filter(|k| k.is_mineable())?.hardness();
+        let done = u32::from(self.damage_at(c)).saturating_add(power);
+        if done >= u32::from(hardness) {
+            return self.mine_tile(c);
+        }
+        if power > 0 {
+            self.damage.insert(c, done as u8);
+            self.edited.push(c);
+        }
+        None
+    }
+
+    pub fn damage_at(&self, c: TileCoord3) -> u8 {
+        self.damage.get(&c).copied().unwrap_or(0)
+    }
+
+    // How far along a partly mined tile is, from 0.0 to just under 1.0.
+    pub fn mined_fraction(&self, c: TileCoord3) -> f32 {
+        match self.get_tile(c).map(TileKind::hardness) {
+            Some(h) if h > 0 => f32::from(self.damage_at(c)) / f32::from(h),
+            _ => 0.0,
+        }
+    }
+
+    pub fn add_stockpile(&mut self, zone: TileBox3) {
+        self.stockpiles.push(zone);
+        // Anything already lying in the new zone is now stockpiled
+        let inside: Vec<_> = self
+            .ground_items
+            .keys()
+            .copied()
+            .filter(|c| zone.contains(*c))
+            .collect();
+        for c in inside {
+            let items = self.take_items(c);
+            self.resources.add(&items);
+        }
+    }
+
+    pub fn stockpiles(&self) -> &[TileBox3] {
+        &self.stockpiles
+    }
+
+    pub fn is_stockpile(&self, c: TileCoord3) -> bool {
+        self.stockpiles.iter().any(|z| z.contains(c))
+    }
+
+    // Where drones can unload: the core and every stockpile tile.
+    pub fn is_drop_off(&self, c: TileCoord3) -> bool {
+        self.core == Some(c) || self.is_stockpile(c)
+    }
+
+    pub fn drop_off_tiles(&self) -> Vec<TileCoord3> {
+        self.core
+            .into_iter()
+            .chain(self.stockpiles.iter().flat_map(|z| z.iter_tiles()))
+            .collect()
+    }
+
+    pub fn nearest_drop_off(&self, from: TileCoord3) -> Option<TileCoord3> {
+        self.drop_off_tiles()
+            .into_iter()
+            .min_by_key(|c| c.manhattan(from))
+    }
+
+    // Closest stockpile tile to `from` by Manhattan distance.
+    pub fn nearest_stockpile_tile(&self, from: TileCoord3) -> Option<TileCoord3> {
+        self.stockpiles
+            .iter()
+            .flat_map(|z| z.iter_tiles())
+            .min_by_key(|c| c.manhattan(from))
+    }
+
+    // Items dropped on the core or inside a stockpile zone are counted into
+    // `resources`; anywhere else they form a ground pile waiting to be hauled.
+    pub fn drop_items(&mut self, c: TileCoord3, items: Resources) {
+        if items.is_empty() || self.index(c).is_none() {
+            return;
+        }
+        if self.is_drop_off(c) {
+            self.resources.add(&items);
         } else {
-            None
+            self.ground_items.entry(c).or_default().add(&items);
         }
     }
+
+    pub fn items_at(&self, c: TileCoord3) -> Resources {
+        self.ground_items.get(&c).copied().unwrap_or_default()
+    }
+
+    pub fn take_items(&mut self, c: TileCoord3) -> Resources {
+        self.ground_items.remove(&c).unwrap_or_default()
+    }
+
+    pub fn ground_items(&self) -> impl Iterator<Item = (TileCoord3, Resources)> + '_ {
+        self.ground_items.iter().map(|(c, r)| (*c, *r))
+    }
+
+    pub fn ground_total(&self) -> Resources {
+        let mut total = Resources::default();
+        for r in self.ground_items.values() {
+            total.add(r);
+        }
+        total
+    }
 }
 
 #[cfg(test)]
@@ -119,14 +440,88 @@ mod tests {
     }
 
     #[test]
-    fn mining_updates_resources() {
+    fn change_log_tracks_passability() {
+        let mut w = World::new(3, 1, 1, TileKind::Stone);
+        let rev = w.revision();
+        w.mine_tile(TileCoord3::new(0, 0, 0));
+        // Stone to Wall doesn't change how drones move
+        w.set_tile(TileCoord3::new(1, 0, 0), TileKind::Wall);
+        w.set_tile(TileCoord3::new(2, 0, 0), TileKind::Stairs);
+        assert_eq!(
+            w.changes_since(rev),
+            Some(&[TileCoord3::new(0, 0, 0), TileCoord3::new(2, 0, 0)][..])
+        );
+        assert_eq!(w.changes_since(w.revision()), Some(&[][..]));
+    }
+
+    #[test]
+    fn mining_hands_back_the_yield() {
         let mut w = World::new(2, 1, 1, TileKind::Air);
         let c = TileCoord3 { x: 0, y: 0, z: 0 };
         w.set_tile(c, TileKind::Stone);
         let y = w.mine_tile(c);
-        assert!(y.is_some());
+        assert_eq!(y, Some(ResourceYield::Stone(1)));
         assert_eq!(w.get_tile(c), Some(TileKind::Air));
+        // Nothing lands anywhere until the miner drops it
+        assert!(w.items_at(c).is_empty());
+        assert_eq!(w.resources.stone, 0);
+        assert_eq!(w.mine_tile(c), None);
+    }
+
+    #[test]
+    fn digging_takes_hardness_into_account() {
+        let mut w = World::new(2, 1, 1, TileKind::Iron);
+        let c = TileCoord3::new(0, 0, 0);
+        // Iron has hardness 4: a power-1 drone needs four ticks
+        for _ in 0..3 {
+            assert_eq!(w.dig_tile(c, 1), None);
+        }
+        assert_eq!(w.damage_at(c), 3);
+        assert_eq!(w.mined_fraction(c), 0.75);
+        assert_eq!(w.dig_tile(c, 1), Some(ResourceYield::Iron(1)));
+        assert_eq!(w.damage_at(c), 0);
+        // Enough power clears it in one go
+        assert!(w.dig_tile(TileCoord3::new(1, 0, 0), 4).is_some());
+        assert_eq!(w.dig_tile(c, 10), None);
+    }
+
+    #[test]
+    fn stockpiled_items_count_as_resources() {
+        let mut w = World::new(4, 1, 1, TileKind::Iron);
+        let a = TileCoord3 { x: 0, y: 0, z: 0 };
+        let b = TileCoord3 { x: 3, y: 0, z: 0 };
+        let y = w.mine_tile(a).unwrap();
+        w.drop_items(a, y.as_resources());
+        assert_eq!(w.items_at(a), Resources { stone: 0, iron: 1 });
+        w.add_stockpile(TileBox3::new(b, b));
+        assert_eq!(w.nearest_stockpile_tile(a), Some(b));
+        // Loose items count once they are dropped in the zone
+        let items = w.take_items(a);
+        w.drop_items(b, items);
+        assert_eq!(w.resources.iron, 1);
+        assert!(w.ground_total().is_empty());
+        // The core takes deliveries too
+        let core = TileCoord3 { x: 1, y: 0, z: 0 };
+        w.set_core(core);
+        assert_eq!(w.nearest_drop_off(a), Some(core));
+        w.drop_items(core, Resources { stone: 2, iron: 0 });
+        assert_eq!(w.resources.stone, 2);
+    }
+
+    #[test]
+    fn building_spends_resources() {
+        let mut w = World::new(2, 1, 1, TileKind::Air);
+        let a = TileCoord3 { x: 0, y: 0, z: 0 };
+        let b = TileCoord3 { x: 1, y: 0, z: 0 };
+        w.resources.add_stone(3);
+        assert!(w.build_tile(a, TileKind::Wall));
+        assert_eq!(w.get_tile(a), Some(TileKind::Wall));
         assert_eq!(w.resources.stone, 1);
-        assert_eq!(w.resources.iron, 0);
+        // Not enough stone left for a second wall, and walls can't be rebuilt
+        assert!(!w.build_tile(b, TileKind::Wall));
+        assert!(!w.build_tile(a, TileKind::Wall));
+        assert_eq!(w.get_tile(b), Some(TileKind::Air));
+        assert!(w.build_tile(b, TileKind::Floor));
+        assert_eq!(w.resources.stone, 0);
     }
 }
@@ -0,0 +1,242 @@
+// Plays many seeds with random player input and checks that every way of
+// re-running a game lands on the same state hash, tick for tick.
+use droneforge::history::{DEFAULT_HISTORY_BUDGET, DEFAULT_SNAPSHOT_INTERVAL};
+use droneforge::*;
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use serde_json::json;
+
+const SIZE: i32 = 24;
+const TICKS: u64 = 200;
+
+fn start(seed: u64) -> Engine {
+    let c = SIZE / 2;
+    let at = |x: i32, y: i32| json!({ "x": x, "y": y, "z": 0 });
+    let fill = |min, max, tile| json!({ "area": { "min": min, "max": max }, "tile": tile });
+    let scenario = Scenario::from_json(
+        &json!({
+            "name": format!("fuzz {}", seed),
+            "map": { "kind": "Generated", "width": SIZE, "height": SIZE, "seed": seed },
+            "areas": [
+                fill(at(c - 5, c - 5), at(c + 5, c + 5), "Floor"),
+                // Chargers about, so a drone running low picks the nearest
+                fill(at(c - 5, c + 5), at(c - 5, c + 5), "Charger"),
+                fill(at(c + 5, c - 5), at(c + 5, c - 5), "Charger"),
+                fill(at(c - 1, c - 5), at(c - 1, c - 5), "Charger"),
+                fill(at(c + 5, c + 1), at(c + 5, c + 1), "Charger")
+            ],
+            "resources": { "stone": 30, "iron": 20 },
+            // Drop-offs too: the core and every stockpile tile
+            "stockpiles": [
+                { "min": at(c - 1, c - 1), "max": at(c + 1, c + 1) },
+                { "min": at(c + 2, c + 2), "max": at(c + 3, c + 3) }
+            ],
+            "drones": [
+                { "type": "miner", "at": at(c - 2, c) },
+                { "type": "miner", "at": at(c - 2, c + 2) },
+                { "type": "builder", "at": at(c + 2, c) },
+                { "type": "hauler", "at": at(c, c - 2) },
+                { "type": "hauler", "at": at(c - 2, c - 2) },
+                { "at": at(c, c + 2) }
+            ]
+        })
+        .to_string(),
+    )
+    .unwrap();
+    let mut engine = scenario.build().unwrap();
+    engine.reseed(seed);
+    for d in &mut engine.drones {
+        // Worn drones make breakdowns, and so the RNG, part of every run
+        d.durability.current = 40;
+        // Small batteries send drones off to recharge mid-task
+        d.battery = Battery::new(40);
+    }
+    engine
+}
+
+fn random_box(rng: &mut StdRng) -> TileBox3 {
+    let x = rng.gen_range(2..SIZE - 4);
+    let y = rng.gen_range(2..SIZE - 4);
+    let max = TileCoord3::new(x + rng.gen_range(0..3), y + rng.gen_range(0..3), 0);
+    TileBox3::new(TileCoord3::new(x, y, 0), max)
+}
+
+fn random_command(rng: &mut StdRng, engine: &Engine) -> Command {
+    let task_id = rng.gen_range(0..engine.tasks.tasks.len().max(1));
+    let drone = rng.gen_range(1..=engine.drones.len() as u32);
+    match rng.gen_range(0..10) {
+        0..=3 => Command::QueueTask {
+            task: Task::MineBox(random_box(rng)),
+            drone: rng.gen_bool(0.3).then_some(drone),
+        },
+        4 => Command::QueueTask {
+            task: Task::BuildWall(random_box(rng)),
+            drone: None,
+        },
+        5 => Command::QueueTask {
+            task: Task::Haul(random_box(rng)),
+            drone: None,
+        },
+        // Trips across the open middle, some of them to a charger that
+        // isn't the nearest for drones along the way
+        6 if rng.gen_bool(0.6) => {
+            let c = SIZE / 2;
+            let chargers = engine.world.chargers();
+            let to = if rng.gen_bool(0.5) {
+                chargers[rng.gen_range(0..chargers.len())]
+            } else {
+                TileCoord3::new(c + rng.gen_range(-5..=5), c + rng.gen_range(-5..=5), 0)
+            };
+            Command::QueueTask {
+                task: Task::MoveTo(to),
+                drone: rng.gen_bool(0.5).then_some(drone),
+            }
+        }
+        6 => Command::CancelTask(task_id),
+        7 => Command::AssignTask {
+            task: task_id,
+            drone,
+        },
+        8 => Command::SetRole {
+            drone,
+            role: [None, Some(TaskKind::Mine), Some(TaskKind::Haul)][rng.gen_range(0..3)],
+        },
+        _ => Command::Fabricate(["miner", "hauler"][rng.gen_range(0..2)].to_string()),
+    }
+}
+
+// Ticks a game, issuing random commands between ticks, and returns the
+// state hash after every tick.
+fn play(engine: &mut Engine, seed: u64, ticks: u64) -> Vec<u64> {
+    let mut rng = StdRng::seed_from_u64(seed ^ 0x5eed);
+    let mut hashes = Vec::new();
+    for _ in 0..ticks {
+        if rng.gen_bool(0.2) {
+            let command = random_command(&mut rng, engine);
+            let _ = engine.apply(command);
+        }
+        engine.tick();
+        hashes.push(engine.state_hash());
+    }
+    hashes
+}
+
+#[test]
+fn identical_runs_hash_identically() {
+    let mut finals = Vec::new();
+    for seed in 0..6 {
+        let a = play(&mut start(seed), seed, TICKS);
+        let b = play(&mut start(seed), seed, TICKS);
+        if let Some(tick) = a.iter().zip(&b).position(|(x, y)| x != y) {
+            panic!("seed {} diverged at tick {}", seed, tick + 1);
+        }
+        finals.push(a[a.len() - 1]);
+    }
+    // And different seeds really are different games
+    finals.sort();
+    finals.dedup();
+    assert_eq!(finals.len(), 6);
+}
+
+#[test]
+fn replays_reproduce_random_sessions() {
+    for seed in 0..4 {
+        let mut engine = start(seed);
+        engine.start_recording(10);
+        play(&mut engine, seed, TICKS);
+        let replay = engine.stop_recording().unwrap();
+        let bytes = replay.to_bytes(SaveFormat::Binary).unwrap();
+        let rerun = Replay::from_bytes(&bytes)
+            .unwrap()
+            .run()
+            .unwrap_or_else(|e| panic!("seed {}: {}", seed, e));
+        assert_eq!(rerun.state_hashes(), engine.state_hashes());
+    }
+}
+
+#[test]
+fn saved_games_continue_like_live_ones() {
+    for seed in 0..5 {
+        // Every tick is checked against a copy restored from a save
+        let mut engine = start(seed);
+        engine.set_determinism_check(true);
+        play(&mut engine, seed, TICKS);
+    }
+}
+
+#[test]
+fn rollbacks_return_to_earlier_states() {
+    for seed in 0..3 {
+        let mut engine = start(seed);
+        engine.enable_history(DEFAULT_SNAPSHOT_INTERVAL, DEFAULT_HISTORY_BUDGET);
+        let hashes = play(&mut engine, seed, TICKS);
+        for tick in [TICKS - 1, TICKS / 2 + 7, 13] {
+            engine.restore_to_tick(tick).unwrap();
+            let expected = hashes[tick as usize - 1];
+            assert_eq!(engine.state_hash(), expected, "seed {} tick {}", seed, tick);
+        }
+    }
+}
+
+#[test]
+fn undo_and_redo_rewrite_the_present() {
+    for seed in 0..3 {
+        let mut with = start(seed);
+        let mut without = start(seed);
+        with.enable_history(DEFAULT_SNAPSHOT_INTERVAL, DEFAULT_HISTORY_BUDGET);
+        play(&mut with, seed, TICKS / 2);
+        play(&mut without, seed, TICKS / 2);
+        let dig = Command::QueueTask {
+            task: Task::MineBox(TileBox3::new(
+                TileCoord3::new(2, 2, 0),
+                TileCoord3::new(SIZE - 3, SIZE - 3, 0),
+            )),
+            drone: None,
+        };
+        with.apply(dig).unwrap();
+        for _ in 0..30 {
+            with.tick();
+            without.tick();
+        }
+        let done = with.state_hash();
+        assert_ne!(done, without.state_hash());
+
+        assert!(matches!(with.undo(), Ok(Command::QueueTask { .. })));
+        assert_eq!(with.state_hash(), without.state_hash(), "seed {}", seed);
+        // A command that fails changes nothing, so the dig can still come back
+        assert!(with.apply(Command::CancelTask(usize::MAX)).is_err());
+        assert!(matches!(with.redo(), Ok(Command::QueueTask { .. })));
+        assert_eq!(with.state_hash(), done, "seed {}", seed);
+    }
+}
+
+#[test]
+fn thread_count_does_not_change_the_game() {
+    // A crowd big enough for path planning to be split between threads
+    let crowd = |seed: u64| {
+        let mut engine = start(seed);
+        let c = SIZE / 2;
+        for (i, (dx, dy)) in (-3..=3)
+            .flat_map(|x| (-3..=3).map(move |y| (x, y)))
+            .enumerate()
+        {
+            let id = 100 + i as u32;
+            engine
+                .drones
+                .push(Drone::new(id).at(TileCoord3::new(c + dx, c + dy, 0)));
+        }
+        engine
+    };
+    let pool = |threads: usize| {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .unwrap()
+    };
+    for seed in 0..2 {
+        let serial = pool(1).install(|| play(&mut crowd(seed), seed, TICKS));
+        let parallel = pool(4).install(|| play(&mut crowd(seed), seed, TICKS));
+        if let Some(tick) = serial.iter().zip(&parallel).position(|(x, y)| x != y) {
+            panic!("seed {} diverged at tick {}", seed, tick + 1);
+        }
+    }
+}
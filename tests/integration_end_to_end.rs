@@ -3,8 +3,13 @@ use serde_json::json;
 
 #[test]
 fn end_to_end_mining_from_ast() {
-    // Build a small world with stone
-    let world = World::new(3, 3, 1, TileKind::Stone);
+    // Build a small world with stone and a stockpile along one edge
+    let mut world = World::new(3, 3, 1, TileKind::Stone);
+    let stockpile = TileBox3::new(TileCoord3::new(0, 2, 0), TileCoord3::new(2, 2, 0));
+    for c in stockpile.iter_tiles() {
+        world.set_tile(c, TileKind::Floor);
+    }
+    world.add_stockpile(stockpile);
     let mut engine = Engine::new(world, vec![Drone::new(1)]);
 
     // AST program: let area; mine_box(area)
@@ -29,18 +34,38 @@ fn end_to_end_mining_from_ast() {
                     "func": "mine_box",
                     "args": [{ "node": "VarRef", "name": "area" }]
                 }
+            },
+            {
+                "node": "ExprStmt",
+                "expr": {
+                    "node": "Call",
+                    "func": "haul",
+                    "args": [{ "node": "VarRef", "name": "area" }]
+                }
             }
         ]
     });
     let prog: Program = serde_json::from_value(program_json).unwrap();
     let tasks = compile_program_to_tasks(&prog).unwrap();
-    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks.len(), 2);
     for t in tasks {
         engine.tasks.push(t);
     }
 
-    // Run one engine tick (M1 applies task immediately)
-    engine.tick();
+    // The drone mines one tile per tick into its cargo, moving into the
+    // dug-out space as needed, then unloads at the stockpile
+    for _ in 0..10 {
+        if engine.tasks.state(0) == Some(TaskState::Done) {
+            break;
+        }
+        engine.tick();
+    }
+    assert_eq!(engine.tasks.state(0), Some(TaskState::Done));
+    assert!(engine.drones[0].cargo.is_empty());
+    assert!(engine.world.ground_total().is_empty());
+    for _ in 0..10 {
+        engine.tick();
+    }
 
     // Verify resources and UI strings
     assert_eq!(engine.world.resources.stone, 4);